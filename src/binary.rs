@@ -0,0 +1,181 @@
+use anyhow::{Result, bail};
+use rand::Rng;
+use std::collections::HashMap;
+
+use crate::locales::{Locale, localized};
+use crate::option_parser;
+
+/// What a bytes field holds, from its `bytes` option
+#[derive(Debug, Clone, PartialEq)]
+pub enum BytesMode {
+    Random,
+    /// Words in the message's locale, encoded as UTF-8
+    Utf8,
+    /// A version 4 UUID in its 16-byte binary form
+    Uuid,
+    /// 32 random bytes, the size of a SHA-256 digest
+    Sha256,
+    /// A small valid PNG image
+    Png,
+    /// Values of a pool, with strings encoded as UTF-8
+    Pool(String),
+}
+
+impl BytesMode {
+    pub fn from_options(options: &HashMap<String, option_parser::Value>) -> Result<Self> {
+        let mode = match options.get("bytes") {
+            Some(option_parser::Value::Str(mode)) => mode.as_str(),
+            Some(mode) => bail!("bytes must be a content mode like utf8, got {:?}", mode),
+            None => return Ok(BytesMode::Random),
+        };
+
+        Ok(match mode {
+            "random" => BytesMode::Random,
+            "utf8" => BytesMode::Utf8,
+            "uuid" => BytesMode::Uuid,
+            "sha256" => BytesMode::Sha256,
+            "png" => BytesMode::Png,
+            mode => match mode.strip_prefix("pool:") {
+                Some(name) if !name.is_empty() => BytesMode::Pool(name.to_string()),
+                _ => bail!(
+                    "Unknown bytes mode {:?}, expected random, utf8, uuid, sha256, png or \
+                     pool:NAME",
+                    mode
+                ),
+            },
+        })
+    }
+
+    /// Whether the length options choose the size of values
+    pub fn sized(&self) -> bool {
+        matches!(self, BytesMode::Random | BytesMode::Utf8)
+    }
+}
+
+/// Exactly `len` bytes of UTF-8 text: words, with the last one cut at a character boundary
+/// and padded with letters if needed
+pub fn utf8<R: Rng + ?Sized>(locale: Locale, len: usize, rng: &mut R) -> Vec<u8> {
+    let mut text = String::new();
+    while text.len() < len {
+        if !text.is_empty() {
+            text.push(' ');
+        }
+        let word: String = localized!(locale, lorem::Word(), rng);
+        text.push_str(&word);
+    }
+
+    let mut end = len;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    text.truncate(end);
+    text.extend((end..len).map(|_| rng.random_range('a'..='z')));
+    text.into_bytes()
+}
+
+pub fn uuid<R: Rng + ?Sized>(rng: &mut R) -> Vec<u8> {
+    uuid::Builder::from_random_bytes(rng.random())
+        .into_uuid()
+        .as_bytes()
+        .to_vec()
+}
+
+/// A PNG of 1 to 8 pixels square with random colors, stored without compression
+pub fn png<R: Rng + ?Sized>(rng: &mut R) -> Vec<u8> {
+    let size: u8 = rng.random_range(1..=8);
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&u32::from(size).to_be_bytes());
+    header.extend_from_slice(&u32::from(size).to_be_bytes());
+    // 8-bit RGB, default compression and filtering, not interlaced
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    // Each row starts with filter type 0 (none)
+    let mut pixels = Vec::new();
+    for _ in 0..size {
+        pixels.push(0);
+        pixels.extend((0..usize::from(size) * 3).map(|_| rng.random::<u8>()));
+    }
+
+    // A zlib stream with a single stored deflate block
+    let mut data = vec![0x78, 0x01, 0x01];
+    let len = pixels.len() as u16;
+    data.extend_from_slice(&len.to_le_bytes());
+    data.extend_from_slice(&(!len).to_le_bytes());
+    data.extend_from_slice(&pixels);
+    data.extend_from_slice(&adler32(&pixels).to_be_bytes());
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    for (kind, body) in [(b"IHDR", header), (b"IDAT", data), (b"IEND", Vec::new())] {
+        png.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        let start = png.len();
+        png.extend_from_slice(kind);
+        png.extend_from_slice(&body);
+        let crc = crc32(&png[start..]);
+        png.extend_from_slice(&crc.to_be_bytes());
+    }
+    png
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + u32::from(byte)) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::option_parser::parse_options;
+
+    #[test]
+    fn test_modes() -> Result<()> {
+        let mode = |text| BytesMode::from_options(&parse_options(text));
+        assert_eq!(mode("len=4")?, BytesMode::Random);
+        assert_eq!(mode("bytes=sha256")?, BytesMode::Sha256);
+        assert_eq!(
+            mode("bytes=pool:avatars")?,
+            BytesMode::Pool("avatars".to_string())
+        );
+        assert!(mode("bytes=pool:").is_err());
+        assert!(mode("bytes=jpeg").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate() {
+        let mut rng = rand::rng();
+        for len in [0, 1, 7, 40] {
+            let text = utf8(Locale::JaJp, len, &mut rng);
+            assert_eq!(text.len(), len);
+            assert!(String::from_utf8(text).is_ok());
+        }
+
+        let id = uuid::Uuid::from_slice(&uuid(&mut rng)).unwrap();
+        assert_eq!(id.get_version_num(), 4);
+
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+        let png = png(&mut rng);
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR"));
+        assert!(png.ends_with(b"IEND\xae\x42\x60\x82"));
+    }
+}
@@ -1,5 +1,15 @@
-use rand::RngCore;
-use rand_distr::{Distribution, LogNormal, Normal, Pareto};
+use anyhow::{Context, Result, bail};
+use rand::distr::weighted::WeightedIndex;
+use rand::{Rng, RngCore};
+use rand_distr::{
+    Bernoulli, Beta, Distribution, Exp, Gamma, LogNormal, Normal, Pareto, Poisson, Triangular,
+    Weibull, Zipf,
+};
+
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::option_parser;
 
 /// A random generator that produces biased samples normalized into [0, 1).
 pub trait BiasedRng: RngCore {
@@ -9,72 +19,295 @@ pub trait BiasedRng: RngCore {
     fn _sample(&mut self) -> f64;
 }
 
-pub struct ParetoRng<R: RngCore> {
-    inner: R,
-    pareto: Pareto<f64>,
+/// A `distribution` option with its parameters checked, ready to sample from
+#[derive(Debug, Clone)]
+pub enum Sampler {
+    Uniform,
+    Normal(Normal<f64>),
+    LogNormal(LogNormal<f64>),
+    Pareto(Pareto<f64>),
+    Exponential(Exp<f64>),
+    Zipf(Zipf<f64>, f64),
+    Poisson(Poisson<f64>),
+    Gamma(Gamma<f64>),
+    Beta(Beta<f64>),
+    Triangular(Triangular<f64>, f64, f64),
+    Weibull(Weibull<f64>),
+    Bernoulli(Bernoulli),
+    Categorical(WeightedIndex<f64>, usize),
+    Empirical(Arc<Empirical>),
 }
 
-impl<R: RngCore> ParetoRng<R> {
-    pub fn new(inner: R, scale: f64, shape: f64) -> Self {
-        let pareto = Pareto::new(scale, shape).unwrap();
-        Self { inner, pareto }
+impl Sampler {
+    /// Check a distribution's parameters, reading the file of an `empirical` one
+    pub fn new(distribution: &option_parser::Distribution) -> Result<Self> {
+        use option_parser::Distribution as D;
+
+        let params: &[f64] = match distribution {
+            D::Uniform => &[],
+            D::Normal(a, b)
+            | D::LogNormal(a, b)
+            | D::Pareto(a, b)
+            | D::Zipf(a, b)
+            | D::Gamma(a, b)
+            | D::Beta(a, b)
+            | D::Weibull(a, b) => &[*a, *b],
+            D::Exponential(a) | D::Poisson(a) | D::Bernoulli(a) => &[*a],
+            D::Triangular(a, b, c) => &[*a, *b, *c],
+            D::Categorical(weights) => weights,
+            D::Empirical(_) => &[],
+        };
+        if let Some(param) = params.iter().find(|param| !param.is_finite()) {
+            bail!("distribution parameters must be finite, got {}", param);
+        }
+
+        Ok(match *distribution {
+            D::Uniform => Sampler::Uniform,
+            D::Normal(_, std_dev) | D::LogNormal(_, std_dev) if std_dev < 0.0 => {
+                bail!("standard deviation must not be negative, got {}", std_dev)
+            }
+            D::Normal(mean, std_dev) => Sampler::Normal(
+                Normal::new(mean, std_dev)
+                    .map_err(|e| anyhow::anyhow!("normal({},{}): {}", mean, std_dev, e))?,
+            ),
+            D::LogNormal(mean, std_dev) => Sampler::LogNormal(
+                LogNormal::new(mean, std_dev)
+                    .map_err(|e| anyhow::anyhow!("log_normal({},{}): {}", mean, std_dev, e))?,
+            ),
+            D::Pareto(scale, shape) => Sampler::Pareto(
+                Pareto::new(scale, shape)
+                    .map_err(|e| anyhow::anyhow!("pareto({},{}): {}", scale, shape, e))?,
+            ),
+            D::Exponential(lambda) if lambda <= 0.0 => {
+                bail!("exponential rate must be positive, got {}", lambda)
+            }
+            D::Exponential(lambda) => Sampler::Exponential(
+                Exp::new(lambda).map_err(|e| anyhow::anyhow!("exponential({}): {}", lambda, e))?,
+            ),
+            D::Zipf(n, _) if n < 1.0 || n.fract() != 0.0 => {
+                bail!("zipf needs a whole number of ranks, got {}", n)
+            }
+            D::Zipf(n, s) => Sampler::Zipf(
+                Zipf::new(n, s).map_err(|e| anyhow::anyhow!("zipf({},{}): {}", n, s, e))?,
+                n,
+            ),
+            D::Poisson(lambda) => Sampler::Poisson(
+                Poisson::new(lambda).map_err(|e| anyhow::anyhow!("poisson({}): {}", lambda, e))?,
+            ),
+            D::Gamma(shape, scale) => Sampler::Gamma(
+                Gamma::new(shape, scale)
+                    .map_err(|e| anyhow::anyhow!("gamma({},{}): {}", shape, scale, e))?,
+            ),
+            D::Beta(alpha, beta) => Sampler::Beta(
+                Beta::new(alpha, beta)
+                    .map_err(|e| anyhow::anyhow!("beta({},{}): {}", alpha, beta, e))?,
+            ),
+            D::Triangular(min, max, _) if min >= max => {
+                bail!("triangular needs min below max, got {} and {}", min, max)
+            }
+            D::Triangular(min, max, mode) => Sampler::Triangular(
+                Triangular::new(min, max, mode)
+                    .map_err(|e| anyhow::anyhow!("triangular({},{},{}): {}", min, max, mode, e))?,
+                min,
+                max,
+            ),
+            D::Weibull(scale, shape) => Sampler::Weibull(
+                Weibull::new(scale, shape)
+                    .map_err(|e| anyhow::anyhow!("weibull({},{}): {}", scale, shape, e))?,
+            ),
+            D::Bernoulli(p) => Sampler::Bernoulli(
+                Bernoulli::new(p).map_err(|e| anyhow::anyhow!("bernoulli({}): {}", p, e))?,
+            ),
+            D::Categorical(ref weights) => Sampler::Categorical(
+                WeightedIndex::new(weights)
+                    .map_err(|e| anyhow::anyhow!("categorical({:?}): {}", weights, e))?,
+                weights.len(),
+            ),
+            D::Empirical(ref path) => {
+                Sampler::Empirical(Arc::new(Empirical::load(Path::new(path))?))
+            }
+        })
     }
 }
 
-impl<R: RngCore> BiasedRng for ParetoRng<R> {
-    fn sample01(&mut self) -> f64 {
-        let sample = self._sample();
-        sample / (sample + 1.0)
+impl Sampler {
+    /// A sample in the distribution's own units. Discrete distributions give their outcome:
+    /// the rank for `zipf`, 0 or 1 for `bernoulli` and the index of the weight for
+    /// `categorical`.
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        match self {
+            Sampler::Uniform => rng.random(),
+            Sampler::Normal(normal) => normal.sample(rng),
+            Sampler::LogNormal(lognormal) => lognormal.sample(rng),
+            Sampler::Pareto(pareto) => pareto.sample(rng),
+            Sampler::Exponential(exp) => exp.sample(rng),
+            Sampler::Zipf(zipf, _) => zipf.sample(rng),
+            Sampler::Poisson(poisson) => poisson.sample(rng),
+            Sampler::Gamma(gamma) => gamma.sample(rng),
+            Sampler::Beta(beta) => beta.sample(rng),
+            Sampler::Triangular(triangular, _, _) => triangular.sample(rng),
+            Sampler::Weibull(weibull) => weibull.sample(rng),
+            Sampler::Bernoulli(bernoulli) => f64::from(u8::from(bernoulli.sample(rng))),
+            Sampler::Categorical(index, _) => index.sample(rng) as f64,
+            Sampler::Empirical(empirical) => empirical.sample(rng),
+        }
     }
 
-    fn _sample(&mut self) -> f64 {
-        self.pareto.sample(&mut self.inner)
+    /// A sample within `[lo, hi]`. Samples outside are drawn again, which truncates the
+    /// distribution; if the bounds are too far in a tail for that, the last one is clamped.
+    pub fn sample_within<R: Rng + ?Sized>(&self, lo: f64, hi: f64, rng: &mut R) -> f64 {
+        let mut sample = self.sample(rng);
+        for _ in 1..TRUNCATION_ATTEMPTS {
+            if (lo..=hi).contains(&sample) {
+                return sample;
+            }
+            sample = self.sample(rng);
+        }
+        sample.clamp(lo, hi)
     }
 }
 
-pub struct NormalRng<R: RngCore> {
+/// How many samples `sample_within` draws before clamping
+const TRUNCATION_ATTEMPTS: usize = 100;
+
+/// An Rng biased by a `distribution`: each draw is a sample normalized into [0, 1), so
+/// ranges are picked from with the distribution's shape
+pub struct DistributionRng<R: RngCore> {
     inner: R,
-    normal: Normal<f64>,
+    sampler: Arc<Sampler>,
 }
 
-impl<R: RngCore> NormalRng<R> {
-    pub fn new(inner: R, mean: f64, std_dev: f64) -> Self {
-        let normal = Normal::new(mean, std_dev).unwrap();
-        Self { inner, normal }
+impl<R: RngCore> DistributionRng<R> {
+    pub fn new(inner: R, sampler: impl Into<Arc<Sampler>>) -> Self {
+        Self {
+            inner,
+            sampler: sampler.into(),
+        }
     }
 }
 
-impl<R: RngCore> BiasedRng for NormalRng<R> {
+impl<R: RngCore> BiasedRng for DistributionRng<R> {
     fn sample01(&mut self) -> f64 {
         let sample = self._sample();
-        1.0 / (1.0 + (-sample).exp()) // sigmoid(x)
+        let normalized = match self.sampler.as_ref() {
+            Sampler::Normal(_) => 1.0 / (1.0 + (-sample).exp()), // sigmoid(x)
+            // Unbounded positive samples
+            Sampler::LogNormal(_)
+            | Sampler::Pareto(_)
+            | Sampler::Exponential(_)
+            | Sampler::Poisson(_)
+            | Sampler::Gamma(_)
+            | Sampler::Weibull(_) => sample / (sample + 1.0),
+            Sampler::Zipf(_, n) => (sample - 1.0) / n,
+            Sampler::Triangular(_, min, max) => (sample - min) / (max - min),
+            // Discrete samples go to the middle of their share of [0, 1), so that a range
+            // with as many values as the distribution has outcomes gets one per outcome
+            Sampler::Bernoulli(_) => (sample + 0.5) / 2.0,
+            Sampler::Categorical(_, len) => (sample + 0.5) / *len as f64,
+            Sampler::Empirical(empirical) => {
+                let (lo, hi) = empirical.range();
+                if hi > lo {
+                    (sample - lo) / (hi - lo)
+                } else {
+                    0.0
+                }
+            }
+            Sampler::Uniform | Sampler::Beta(_) => sample,
+        };
+        normalized.clamp(0.0, 1.0 - f64::EPSILON)
     }
 
     fn _sample(&mut self) -> f64 {
-        self.normal.sample(&mut self.inner)
+        self.sampler.sample(&mut self.inner)
     }
 }
 
-pub struct LogNormalRng<R: RngCore> {
-    inner: R,
-    lognormal: LogNormal<f64>,
+/// A distribution of observed data, from a CSV file of either `bucket,weight` rows for a
+/// histogram or single values for a list of samples
+#[derive(Debug)]
+pub enum Empirical {
+    /// Buckets by their upper bound, each starting at the previous one's. The first starts at
+    /// 0, or is just its bound if that isn't positive. Values are spread evenly in a bucket.
+    Histogram {
+        /// The first bucket's lower bound, then each bucket's upper bound
+        edges: Vec<f64>,
+        buckets: WeightedIndex<f64>,
+    },
+    /// Sorted samples. Values are interpolated between neighbouring samples.
+    Samples(Vec<f64>),
 }
 
-impl<R: RngCore> LogNormalRng<R> {
-    pub fn new(inner: R, mean: f64, std_dev: f64) -> Self {
-        let lognormal = LogNormal::new(mean, std_dev).unwrap();
-        Self { inner, lognormal }
+impl Empirical {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Can't read empirical distribution {}", path.display()))?;
+        Empirical::parse(&text).with_context(|| format!("In {}", path.display()))
     }
-}
 
-impl<R: RngCore> BiasedRng for LogNormalRng<R> {
-    fn sample01(&mut self) -> f64 {
-        let sample = self._sample();
-        sample / (sample + 1.0)
+    /// Parse CSV rows, skipping blank lines, `#` comments and a header
+    pub(crate) fn parse(text: &str) -> Result<Self> {
+        let mut rows = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let row: Result<Vec<f64>, _> = line.split(',').map(|c| c.trim().parse()).collect();
+            match row {
+                Ok(row) if row.iter().all(|value| value.is_finite()) => rows.push(row),
+                Err(_) if rows.is_empty() => {}
+                _ => bail!("Line {}: expected numbers, got {:?}", number + 1, line),
+            }
+        }
+
+        match rows.first().map(Vec::len) {
+            None => bail!("No data rows"),
+            Some(1) if rows.iter().all(|row| row.len() == 1) => {
+                let mut samples: Vec<f64> = rows.into_iter().map(|row| row[0]).collect();
+                samples.sort_by(f64::total_cmp);
+                Ok(Empirical::Samples(samples))
+            }
+            Some(2) if rows.iter().all(|row| row.len() == 2) => {
+                let bounds: Vec<f64> = rows.iter().map(|row| row[0]).collect();
+                if bounds.windows(2).any(|pair| pair[0] >= pair[1]) {
+                    bail!("Histogram buckets must be in increasing order of their upper bound");
+                }
+                let weights: Vec<f64> = rows.iter().map(|row| row[1]).collect();
+                let buckets =
+                    WeightedIndex::new(&weights).map_err(|e| anyhow::anyhow!("Weights: {}", e))?;
+
+                let mut edges = vec![bounds[0].min(0.0)];
+                edges.extend(bounds);
+                Ok(Empirical::Histogram { edges, buckets })
+            }
+            _ => bail!("Rows must all be bucket,weight or all be single samples"),
+        }
     }
 
-    fn _sample(&mut self) -> f64 {
-        self.lognormal.sample(&mut self.inner)
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        match self {
+            Empirical::Histogram { edges, buckets } => {
+                let bucket = buckets.sample(rng);
+                let (lo, hi) = (edges[bucket], edges[bucket + 1]);
+                lo + rng.random::<f64>() * (hi - lo)
+            }
+            Empirical::Samples(samples) => {
+                let position = rng.random::<f64>() * (samples.len() - 1) as f64;
+                let i = position as usize;
+                match samples.get(i + 1) {
+                    Some(next) => samples[i] + (position - i as f64) * (next - samples[i]),
+                    None => samples[i],
+                }
+            }
+        }
+    }
+
+    /// The smallest and largest values
+    pub fn range(&self) -> (f64, f64) {
+        match self {
+            Empirical::Histogram { edges, .. } => (edges[0], edges[edges.len() - 1]),
+            Empirical::Samples(samples) => (samples[0], samples[samples.len() - 1]),
+        }
     }
 }
 
@@ -106,6 +339,87 @@ macro_rules! impl_biased_rng {
     };
 }
 
-impl_biased_rng!(ParetoRng<R>);
-impl_biased_rng!(NormalRng<R>);
-impl_biased_rng!(LogNormalRng<R>);
+impl_biased_rng!(DistributionRng<R>);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::option_parser::Distribution as D;
+
+    #[test]
+    fn test_samples() {
+        for distribution in [
+            D::Exponential(0.5),
+            D::Zipf(10.0, 1.1),
+            D::Poisson(3.0),
+            D::Gamma(2.0, 2.0),
+            D::Beta(2.0, 5.0),
+            D::Triangular(-1.0, 1.0, 0.0),
+            D::Weibull(1.0, 1.5),
+            D::Bernoulli(0.5),
+            D::Categorical(vec![1.0, 2.0]),
+        ] {
+            let mut rng = DistributionRng::new(rand::rng(), Sampler::new(&distribution).unwrap());
+            for _ in 0..100 {
+                let sample = rng.sample01();
+                assert!(
+                    (0.0..1.0).contains(&sample),
+                    "{:?}: {}",
+                    distribution,
+                    sample
+                );
+            }
+        }
+
+        // Discrete outcomes map one to one onto a range with as many values
+        let sampler = Sampler::new(&D::Categorical(vec![0.0, 1.0, 0.0])).unwrap();
+        let mut rng = DistributionRng::new(rand::rng(), sampler);
+        assert!((0..20).all(|_| rng.random_range(0..3) == 1));
+        let mut rng = DistributionRng::new(rand::rng(), Sampler::new(&D::Bernoulli(1.0)).unwrap());
+        assert!((0..20).all(|_| rng.random_range(5..=6) == 6));
+
+        let normal = Sampler::new(&D::Normal(50.0, 10.0)).unwrap();
+        let mut rng = rand::rng();
+        let mean = (0..1000).map(|_| normal.sample(&mut rng)).sum::<f64>() / 1000.0;
+        assert!((45.0..55.0).contains(&mean), "{}", mean);
+        assert!(
+            (0..100).all(|_| (40.0..=45.0).contains(&normal.sample_within(40.0, 45.0, &mut rng)))
+        );
+        // Far in the tail, samples are clamped
+        assert_eq!(normal.sample_within(500.0, 600.0, &mut rng), 500.0);
+
+        assert!(Sampler::new(&D::Zipf(2.5, 1.0)).is_err());
+        assert!(Sampler::new(&D::Exponential(f64::NAN)).is_err());
+    }
+
+    #[test]
+    fn test_empirical() -> Result<()> {
+        let mut rng = rand::rng();
+
+        let histogram = Empirical::parse("size,weight\n# bytes\n100,1\n1000,0\n10000,3\n")?;
+        assert_eq!(histogram.range(), (0.0, 10000.0));
+        for _ in 0..100 {
+            let size = histogram.sample(&mut rng);
+            assert!((0.0..=100.0).contains(&size) || (1000.0..=10000.0).contains(&size));
+        }
+
+        let samples = Empirical::parse("12\n3\n7.5\n")?;
+        assert_eq!(samples.range(), (3.0, 12.0));
+        assert!((0..100).all(|_| (3.0..=12.0).contains(&samples.sample(&mut rng))));
+        let single = Empirical::parse("latency\n42\n")?;
+        assert_eq!(single.sample(&mut rng), 42.0);
+
+        for text in [
+            "",
+            "a\nb\n",
+            "1,1\n2\n",
+            "10,1\n5,1\n",
+            "1,0\n2,0\n",
+            "1\nx\n",
+        ] {
+            assert!(Empirical::parse(text).is_err(), "{:?}", text);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,220 @@
+use anyhow::{Result, anyhow, bail};
+use prost_reflect::{EnumDescriptor, Syntax};
+use rand::Rng;
+use rand::distr::Distribution;
+use rand::distr::weighted::WeightedIndex;
+use rand::seq::IndexedRandom;
+use std::collections::HashMap;
+
+use crate::option_parser;
+
+/// Largest offset past the highest defined number used for unknown enum values
+const UNKNOWN_SPREAD: i32 = 100;
+
+/// The numbers an enum field can take, built from its `values`, `exclude`, `weights`
+/// and `unknown` options.
+#[derive(Debug, Clone)]
+pub struct EnumChoices {
+    numbers: Vec<i32>,
+    weights: Option<WeightedIndex<f64>>,
+    unknown: f64,
+    max_defined: i32,
+}
+
+impl EnumChoices {
+    pub fn new(
+        enum_type: &EnumDescriptor,
+        options: &HashMap<String, option_parser::Value>,
+    ) -> Result<Self> {
+        // Aliases share a number, keep each number once so they aren't picked more often
+        let mut defined = Vec::new();
+        for value in enum_type.values() {
+            if !defined.contains(&value.number()) {
+                defined.push(value.number());
+            }
+        }
+
+        let mut numbers = match options.get("values") {
+            Some(values) => {
+                let mut numbers = Vec::new();
+                for number in resolve_list(enum_type, values)? {
+                    if !numbers.contains(&number) {
+                        numbers.push(number);
+                    }
+                }
+                numbers
+            }
+            None => defined.clone(),
+        };
+
+        if let Some(exclude) = options.get("exclude") {
+            let exclude = resolve_list(enum_type, exclude)?;
+            numbers.retain(|n| !exclude.contains(n));
+        }
+
+        let weights = match options.get("weights") {
+            Some(option_parser::Value::ListWeighted(items)) => {
+                let mut weighted_numbers = Vec::new();
+                let mut weights = Vec::new();
+                for (name, weight) in items {
+                    let number = resolve_name(enum_type, name)?;
+                    if numbers.contains(&number) && !weighted_numbers.contains(&number) {
+                        weighted_numbers.push(number);
+                        weights.push(*weight);
+                    }
+                }
+                numbers = weighted_numbers;
+                Some(WeightedIndex::new(weights).map_err(|e| {
+                    anyhow!("Invalid weights for enum {}: {}", enum_type.full_name(), e)
+                })?)
+            }
+            Some(_) => bail!("weights must be a list of NAME:weight pairs"),
+            None => None,
+        };
+
+        if numbers.is_empty() {
+            bail!(
+                "No values of enum {} are left to choose from",
+                enum_type.full_name()
+            );
+        }
+
+        let unknown = match options.get("unknown") {
+            Some(option_parser::Value::Float(f)) => *f,
+            Some(option_parser::Value::Int(i)) => *i as f64,
+            Some(option_parser::Value::Bool(b)) => *b as u8 as f64,
+            Some(_) => bail!("unknown must be a probability between 0 and 1"),
+            None => 0.0,
+        };
+        if !(0.0..=1.0).contains(&unknown) {
+            bail!(
+                "unknown must be a probability between 0 and 1, got {}",
+                unknown
+            );
+        }
+        // Closed (proto2) enums can't carry numbers outside their definition
+        if unknown > 0.0 && enum_type.parent_file().syntax() != Syntax::Proto3 {
+            bail!(
+                "unknown values require an open enum, but {} is closed",
+                enum_type.full_name()
+            );
+        }
+
+        Ok(EnumChoices {
+            numbers,
+            weights,
+            unknown,
+            max_defined: defined.iter().copied().max().unwrap_or(0),
+        })
+    }
+
+    /// Pick an enum number
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> i32 {
+        if self.unknown > 0.0 && rng.random_bool(self.unknown) {
+            return self
+                .max_defined
+                .saturating_add(rng.random_range(1..=UNKNOWN_SPREAD));
+        }
+
+        match &self.weights {
+            Some(weights) => self.numbers[weights.sample(rng)],
+            None => *self
+                .numbers
+                .choose(rng)
+                .expect("enum choices are never empty"),
+        }
+    }
+}
+
+fn resolve_list(enum_type: &EnumDescriptor, list: &option_parser::Value) -> Result<Vec<i32>> {
+    match list {
+        option_parser::Value::ListStr(names) => names
+            .iter()
+            .map(|name| resolve_name(enum_type, name))
+            .collect(),
+        option_parser::Value::ListInt(numbers) => Ok(numbers.iter().map(|n| *n as i32).collect()),
+        option_parser::Value::Str(name) => Ok(vec![resolve_name(enum_type, name)?]),
+        option_parser::Value::Int(number) => Ok(vec![*number as i32]),
+        _ => bail!("Expected a list of enum values, got {:?}", list),
+    }
+}
+
+/// Resolve an enum value by its full name, its name without the enum's prefix
+/// (`MOBILE` for `PHONE_TYPE_MOBILE`) or its number.
+pub(crate) fn resolve_name(enum_type: &EnumDescriptor, name: &str) -> Result<i32> {
+    if let Ok(number) = name.parse::<i32>() {
+        return Ok(number);
+    }
+
+    enum_type
+        .get_value_by_name(name)
+        .or_else(|| {
+            enum_type.get_value_by_name(&format!("{}{}", value_prefix(enum_type.name()), name))
+        })
+        .map(|v| v.number())
+        .ok_or_else(|| anyhow!("Enum {} has no value {}", enum_type.full_name(), name))
+}
+
+/// The conventional prefix of an enum's values, e.g. `PHONE_TYPE_` for `PhoneType`
+fn value_prefix(enum_name: &str) -> String {
+    let chars: Vec<char> = enum_name.chars().collect();
+    let mut prefix = String::new();
+
+    for (i, c) in chars.iter().enumerate() {
+        let word_start = i > 0
+            && c.is_uppercase()
+            && (!chars[i - 1].is_uppercase()
+                || chars.get(i + 1).is_some_and(|next| next.is_lowercase()));
+        if word_start {
+            prefix.push('_');
+        }
+        prefix.push(c.to_ascii_uppercase());
+    }
+
+    prefix.push('_');
+    prefix
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto_loader::ProtoLoader;
+
+    #[test]
+    fn test_value_prefix() {
+        assert_eq!(value_prefix("PhoneType"), "PHONE_TYPE_");
+        assert_eq!(value_prefix("HTTPStatus"), "HTTP_STATUS_");
+        assert_eq!(value_prefix("Status"), "STATUS_");
+    }
+
+    #[test]
+    fn test_aliases_are_deduplicated() -> Result<()> {
+        let mut loader = ProtoLoader::new();
+        loader.load_proto_file("proto/enums.proto")?;
+        let message = loader.get_message_descriptor("enums.Order")?;
+        let field = message.get_field_by_name("aliased").unwrap();
+        let enum_type = field.kind().as_enum().unwrap().clone();
+
+        let choices = EnumChoices::new(&enum_type, &HashMap::new())?;
+        assert_eq!(choices.numbers, vec![0, 1, 2, 3]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_options() -> Result<()> {
+        let mut loader = ProtoLoader::new();
+        loader.load_proto_file("proto/enums.proto")?;
+        let message = loader.get_message_descriptor("enums.Order")?;
+        let field = message.get_field_by_name("aliased").unwrap();
+        let enum_type = field.kind().as_enum().unwrap().clone();
+
+        let options = option_parser::parse_options("values=[MISSING]");
+        assert!(EnumChoices::new(&enum_type, &options).is_err());
+
+        let options = option_parser::parse_options("exclude=[0,1,2,3]");
+        assert!(EnumChoices::new(&enum_type, &options).is_err());
+
+        Ok(())
+    }
+}
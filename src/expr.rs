@@ -0,0 +1,593 @@
+use anyhow::{Result, anyhow, bail};
+use chrono::DateTime;
+use prost_reflect::{DynamicMessage, FieldDescriptor, Kind, ReflectMessage, Value};
+use rand::Rng;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+use winnow::ascii::{digit1, multispace0};
+use winnow::combinator::{alt, delimited, opt, preceded, repeat, separated};
+use winnow::error::{ContextError, ErrMode};
+use winnow::prelude::*;
+use winnow::token::{any, none_of, one_of, take_while};
+
+use crate::enums;
+
+/// Functions available in expressions, with their minimum and maximum number of arguments
+const FUNCTIONS: &[(&str, usize, usize)] = &[
+    ("lower", 1, 1),
+    ("upper", 1, 1),
+    ("trim", 1, 1),
+    ("len", 1, 1),
+    ("str", 1, 1),
+    ("concat", 1, usize::MAX),
+    ("substr", 2, 3),
+    ("replace", 3, 3),
+    ("int", 1, 1),
+    ("float", 1, 1),
+    ("abs", 1, 1),
+    ("round", 1, 2),
+    ("floor", 1, 1),
+    ("ceil", 1, 1),
+    ("min", 2, usize::MAX),
+    ("max", 2, usize::MAX),
+    ("random", 2, 2),
+    ("now", 0, 0),
+    ("seconds", 1, 1),
+    ("timestamp", 1, 1),
+    ("duration", 1, 1),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+/// A parsed `expr` option
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Int(i64),
+    Float(f64),
+    Str(String),
+    /// A field reference like `price`, `customer.name` or `parent.order_id`
+    Path(Vec<String>),
+    Neg(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+/// The value of an expression
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprValue {
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Bool(bool),
+    /// Seconds and nanoseconds since the Unix epoch
+    Timestamp(i64, i32),
+}
+
+impl fmt::Display for ExprValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExprValue::Int(i) => write!(f, "{}", i),
+            ExprValue::Float(x) => write!(f, "{}", x),
+            ExprValue::Str(s) => write!(f, "{}", s),
+            ExprValue::Bool(b) => write!(f, "{}", b),
+            ExprValue::Timestamp(seconds, nanos) => {
+                match DateTime::from_timestamp(*seconds, *nanos as u32) {
+                    Some(time) => write!(f, "{}", time.to_rfc3339()),
+                    None => write!(f, "{}", seconds),
+                }
+            }
+        }
+    }
+}
+
+impl Expr {
+    pub fn parse(input: &str) -> Result<Self> {
+        let expr = delimited(multispace0, sum, multispace0)
+            .parse(input)
+            .map_err(|e| anyhow!("Invalid expression {:?}: {}", input, e))?;
+        expr.check_calls()?;
+        Ok(expr)
+    }
+
+    fn check_calls(&self) -> Result<()> {
+        match self {
+            Expr::Int(_) | Expr::Float(_) | Expr::Str(_) | Expr::Path(_) => Ok(()),
+            Expr::Neg(inner) => inner.check_calls(),
+            Expr::Binary(_, lhs, rhs) => {
+                lhs.check_calls()?;
+                rhs.check_calls()
+            }
+            Expr::Call(name, args) => {
+                check_call(name, args.len())?;
+                args.iter().try_for_each(Expr::check_calls)
+            }
+        }
+    }
+
+    /// The field paths the expression reads
+    pub fn paths(&self) -> Vec<&[String]> {
+        match self {
+            Expr::Int(_) | Expr::Float(_) | Expr::Str(_) => vec![],
+            Expr::Path(path) => vec![path.as_slice()],
+            Expr::Neg(inner) => inner.paths(),
+            Expr::Binary(_, lhs, rhs) => {
+                let mut paths = lhs.paths();
+                paths.extend(rhs.paths());
+                paths
+            }
+            Expr::Call(_, args) => args.iter().flat_map(Expr::paths).collect(),
+        }
+    }
+
+    /// Evaluate the expression, reading fields through `lookup`
+    pub fn eval<R: Rng + ?Sized>(
+        &self,
+        lookup: &dyn Fn(&[String]) -> Result<ExprValue>,
+        rng: &mut R,
+    ) -> Result<ExprValue> {
+        match self {
+            Expr::Int(i) => Ok(ExprValue::Int(*i)),
+            Expr::Float(x) => Ok(ExprValue::Float(*x)),
+            Expr::Str(s) => Ok(ExprValue::Str(s.clone())),
+            Expr::Path(path) => lookup(path),
+            Expr::Neg(inner) => match inner.eval(lookup, rng)? {
+                ExprValue::Int(i) => Ok(ExprValue::Int(
+                    i.checked_neg().ok_or_else(|| anyhow!("Integer overflow"))?,
+                )),
+                ExprValue::Float(x) => Ok(ExprValue::Float(-x)),
+                value => bail!("Can't negate {:?}", value),
+            },
+            Expr::Binary(op, lhs, rhs) => {
+                binary(*op, lhs.eval(lookup, rng)?, rhs.eval(lookup, rng)?)
+            }
+            Expr::Call(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| arg.eval(lookup, rng))
+                    .collect::<Result<Vec<_>>>()?;
+                call(name, args, rng)
+            }
+        }
+    }
+}
+
+fn binary(op: Op, lhs: ExprValue, rhs: ExprValue) -> Result<ExprValue> {
+    let overflow = || anyhow!("Integer overflow in {:?}", op);
+
+    match (op, lhs, rhs) {
+        (Op::Add, ExprValue::Str(a), b) => Ok(ExprValue::Str(format!("{}{}", a, b))),
+        (Op::Add, a, ExprValue::Str(b)) => Ok(ExprValue::Str(format!("{}{}", a, b))),
+        (Op::Add, ExprValue::Timestamp(s, n), ExprValue::Int(i))
+        | (Op::Add, ExprValue::Int(i), ExprValue::Timestamp(s, n)) => Ok(ExprValue::Timestamp(
+            s.checked_add(i).ok_or_else(overflow)?,
+            n,
+        )),
+        (Op::Sub, ExprValue::Timestamp(s, n), ExprValue::Int(i)) => Ok(ExprValue::Timestamp(
+            s.checked_sub(i).ok_or_else(overflow)?,
+            n,
+        )),
+        (Op::Sub, ExprValue::Timestamp(a, _), ExprValue::Timestamp(b, _)) => {
+            Ok(ExprValue::Int(a.checked_sub(b).ok_or_else(overflow)?))
+        }
+        (op, ExprValue::Int(a), ExprValue::Int(b)) => {
+            let result = match op {
+                Op::Add => a.checked_add(b),
+                Op::Sub => a.checked_sub(b),
+                Op::Mul => a.checked_mul(b),
+                Op::Div if b == 0 => bail!("Division by zero"),
+                Op::Div => a.checked_div(b),
+                Op::Rem if b == 0 => bail!("Division by zero"),
+                Op::Rem => a.checked_rem(b),
+            };
+            Ok(ExprValue::Int(result.ok_or_else(overflow)?))
+        }
+        (op, a, b) => {
+            let (a, b) = (number(&a)?, number(&b)?);
+            Ok(ExprValue::Float(match op {
+                Op::Add => a + b,
+                Op::Sub => a - b,
+                Op::Mul => a * b,
+                Op::Div => a / b,
+                Op::Rem => a % b,
+            }))
+        }
+    }
+}
+
+fn number(value: &ExprValue) -> Result<f64> {
+    match value {
+        ExprValue::Int(i) => Ok(*i as f64),
+        ExprValue::Float(x) => Ok(*x),
+        value => bail!("Expected a number, got {:?}", value),
+    }
+}
+
+fn integer(value: &ExprValue) -> Result<i64> {
+    match value {
+        ExprValue::Int(i) => Ok(*i),
+        value => bail!("Expected an integer, got {:?}", value),
+    }
+}
+
+/// Check that a function exists and takes `args` arguments
+pub fn check_call(name: &str, args: usize) -> Result<()> {
+    let (_, min, max) = FUNCTIONS
+        .iter()
+        .find(|(function, _, _)| *function == name)
+        .ok_or_else(|| anyhow!("Unknown function {}()", name))?;
+    if args < *min || args > *max {
+        bail!("{}() doesn't take {} arguments", name, args);
+    }
+    Ok(())
+}
+
+/// Apply a function to evaluated arguments, after `check_call` has accepted them
+pub fn call<R: Rng + ?Sized>(name: &str, args: Vec<ExprValue>, rng: &mut R) -> Result<ExprValue> {
+    let text = |i: usize| args[i].to_string();
+
+    match name {
+        "lower" => Ok(ExprValue::Str(text(0).to_lowercase())),
+        "upper" => Ok(ExprValue::Str(text(0).to_uppercase())),
+        "trim" => Ok(ExprValue::Str(text(0).trim().to_string())),
+        "len" => Ok(ExprValue::Int(text(0).chars().count() as i64)),
+        "str" => Ok(ExprValue::Str(text(0))),
+        "concat" => Ok(ExprValue::Str(args.iter().map(|a| a.to_string()).collect())),
+        "substr" => {
+            let start = integer(&args[1])?.max(0) as usize;
+            let len = match args.get(2) {
+                Some(len) => integer(len)?.max(0) as usize,
+                None => usize::MAX,
+            };
+            Ok(ExprValue::Str(
+                text(0).chars().skip(start).take(len).collect(),
+            ))
+        }
+        "replace" => Ok(ExprValue::Str(text(0).replace(&text(1), &text(2)))),
+        "int" => match &args[0] {
+            ExprValue::Int(i) => Ok(ExprValue::Int(*i)),
+            ExprValue::Float(x) => Ok(ExprValue::Int(x.trunc() as i64)),
+            ExprValue::Str(s) => Ok(ExprValue::Int(s.trim().parse()?)),
+            ExprValue::Bool(b) => Ok(ExprValue::Int(*b as i64)),
+            ExprValue::Timestamp(seconds, _) => Ok(ExprValue::Int(*seconds)),
+        },
+        "float" => match &args[0] {
+            ExprValue::Str(s) => Ok(ExprValue::Float(s.trim().parse()?)),
+            value => Ok(ExprValue::Float(number(value)?)),
+        },
+        "abs" => match &args[0] {
+            ExprValue::Int(i) => {
+                Ok(ExprValue::Int(i.checked_abs().ok_or_else(|| {
+                    anyhow!("Integer overflow in abs({})", i)
+                })?))
+            }
+            value => Ok(ExprValue::Float(number(value)?.abs())),
+        },
+        "round" => match args.get(1) {
+            Some(digits) => {
+                let scale = 10f64.powi(integer(digits)? as i32);
+                Ok(ExprValue::Float(
+                    (number(&args[0])? * scale).round() / scale,
+                ))
+            }
+            None => Ok(ExprValue::Int(number(&args[0])?.round() as i64)),
+        },
+        "floor" => Ok(ExprValue::Int(number(&args[0])?.floor() as i64)),
+        "ceil" => Ok(ExprValue::Int(number(&args[0])?.ceil() as i64)),
+        "min" | "max" => {
+            let mut best = args[0].clone();
+            for arg in &args[1..] {
+                let better = match (&best, arg) {
+                    (ExprValue::Int(a), ExprValue::Int(b)) => (b < a) == (name == "min"),
+                    (ExprValue::Timestamp(a, _), ExprValue::Timestamp(b, _)) => {
+                        (b < a) == (name == "min")
+                    }
+                    (a, b) => (number(b)? < number(a)?) == (name == "min"),
+                };
+                if better {
+                    best = arg.clone();
+                }
+            }
+            Ok(best)
+        }
+        "random" => match (&args[0], &args[1]) {
+            (ExprValue::Int(lo), ExprValue::Int(hi)) if lo <= hi => {
+                Ok(ExprValue::Int(rng.random_range(*lo..=*hi)))
+            }
+            (lo, hi) => {
+                let (lo, hi) = (number(lo)?, number(hi)?);
+                if lo.is_nan() || hi.is_nan() || lo > hi {
+                    bail!("random() needs low <= high, got {} and {}", lo, hi);
+                }
+                Ok(ExprValue::Float(rng.random_range(lo..=hi)))
+            }
+        },
+        "now" => {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            Ok(ExprValue::Timestamp(
+                now.as_secs() as i64,
+                now.subsec_nanos() as i32,
+            ))
+        }
+        "seconds" => match &args[0] {
+            ExprValue::Timestamp(seconds, _) => Ok(ExprValue::Int(*seconds)),
+            value => bail!("seconds() expects a timestamp, got {:?}", value),
+        },
+        "timestamp" => Ok(ExprValue::Timestamp(integer(&args[0])?, 0)),
+        "duration" => Ok(ExprValue::Int(parse_duration(&text(0))?)),
+        _ => bail!("Unknown function {}()", name),
+    }
+}
+
+/// Seconds in a duration like `90s`, `15m`, `1h30m` or `2d`
+fn parse_duration(input: &str) -> Result<i64> {
+    let mut seconds = 0i64;
+    let mut digits = String::new();
+
+    for c in input.trim().chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            'w' => 604800,
+            _ => bail!("Invalid duration {:?}", input),
+        };
+        let amount: i64 = digits
+            .parse()
+            .map_err(|_| anyhow!("Invalid duration {:?}", input))?;
+        seconds = amount
+            .checked_mul(unit)
+            .and_then(|amount| seconds.checked_add(amount))
+            .ok_or_else(|| anyhow!("Duration {:?} is too long", input))?;
+        digits.clear();
+    }
+
+    if !digits.is_empty() {
+        bail!("Duration {:?} needs a unit (s, m, h, d or w)", input);
+    }
+    Ok(seconds)
+}
+
+/// Read a field value into an expression value
+pub fn from_value(value: &Value) -> Result<ExprValue> {
+    match value {
+        Value::Bool(b) => Ok(ExprValue::Bool(*b)),
+        Value::I32(i) => Ok(ExprValue::Int(*i as i64)),
+        Value::I64(i) => Ok(ExprValue::Int(*i)),
+        Value::U32(u) => Ok(ExprValue::Int(*u as i64)),
+        Value::U64(u) => Ok(ExprValue::Int(i64::try_from(*u)?)),
+        Value::F32(x) => Ok(ExprValue::Float(*x as f64)),
+        Value::F64(x) => Ok(ExprValue::Float(*x)),
+        Value::String(s) => Ok(ExprValue::Str(s.clone())),
+        Value::Bytes(b) => Ok(ExprValue::Str(String::from_utf8_lossy(b).into_owned())),
+        Value::EnumNumber(n) => Ok(ExprValue::Int(*n as i64)),
+        Value::Message(m) if is_timestamp(m) => Ok(ExprValue::Timestamp(
+            m.get_field_by_name("seconds")
+                .and_then(|s| s.as_i64())
+                .unwrap_or(0),
+            m.get_field_by_name("nanos")
+                .and_then(|n| n.as_i32())
+                .unwrap_or(0),
+        )),
+        Value::Message(m) => bail!(
+            "{} is a message, use a path to one of its fields",
+            m.descriptor().full_name()
+        ),
+        Value::List(_) | Value::Map(_) => bail!("Repeated and map fields can't be used"),
+    }
+}
+
+/// Convert an expression value to the type of `field`
+pub fn to_value(value: ExprValue, field: &FieldDescriptor) -> Result<Value> {
+    let out_of_range = |value: &ExprValue| anyhow!("{} doesn't fit the field", value);
+
+    match (field.kind(), value) {
+        (Kind::Int32 | Kind::Sint32 | Kind::Sfixed32, ExprValue::Int(i)) => Ok(Value::I32(
+            i32::try_from(i).map_err(|_| out_of_range(&ExprValue::Int(i)))?,
+        )),
+        (Kind::Int64 | Kind::Sint64 | Kind::Sfixed64, ExprValue::Int(i)) => Ok(Value::I64(i)),
+        (Kind::Int64 | Kind::Sint64 | Kind::Sfixed64, ExprValue::Timestamp(seconds, _)) => {
+            Ok(Value::I64(seconds))
+        }
+        (Kind::Uint32 | Kind::Fixed32, ExprValue::Int(i)) => Ok(Value::U32(
+            u32::try_from(i).map_err(|_| out_of_range(&ExprValue::Int(i)))?,
+        )),
+        (Kind::Uint64 | Kind::Fixed64, ExprValue::Int(i)) => Ok(Value::U64(
+            u64::try_from(i).map_err(|_| out_of_range(&ExprValue::Int(i)))?,
+        )),
+        (Kind::Double, ExprValue::Int(i)) => Ok(Value::F64(i as f64)),
+        (Kind::Double, ExprValue::Float(x)) => Ok(Value::F64(x)),
+        (Kind::Float, ExprValue::Int(i)) => Ok(Value::F32(i as f32)),
+        (Kind::Float, ExprValue::Float(x)) => Ok(Value::F32(x as f32)),
+        (Kind::Bool, ExprValue::Bool(b)) => Ok(Value::Bool(b)),
+        (Kind::String, value) => Ok(Value::String(value.to_string())),
+        (Kind::Bytes, value) => Ok(Value::Bytes(value.to_string().into_bytes().into())),
+        (Kind::Enum(_), ExprValue::Int(i)) => Ok(Value::EnumNumber(
+            i32::try_from(i).map_err(|_| out_of_range(&ExprValue::Int(i)))?,
+        )),
+        (Kind::Enum(enum_type), ExprValue::Str(name)) => {
+            Ok(Value::EnumNumber(enums::resolve_name(&enum_type, &name)?))
+        }
+        (Kind::Message(message_type), ExprValue::Timestamp(seconds, nanos))
+            if message_type.full_name() == "google.protobuf.Timestamp" =>
+        {
+            let mut timestamp = DynamicMessage::new(message_type);
+            timestamp.set_field_by_name("seconds", Value::I64(seconds));
+            timestamp.set_field_by_name("nanos", Value::I32(nanos));
+            Ok(Value::Message(timestamp))
+        }
+        (Kind::Message(message_type), ExprValue::Int(seconds))
+            if message_type.full_name() == "google.protobuf.Timestamp" =>
+        {
+            to_value(ExprValue::Timestamp(seconds, 0), field)
+        }
+        (kind, value) => bail!("Can't store {:?} in a {:?} field", value, kind),
+    }
+}
+
+fn is_timestamp(message: &DynamicMessage) -> bool {
+    message.descriptor().full_name() == "google.protobuf.Timestamp"
+}
+
+type PResult<T> = winnow::error::ModalResult<T>;
+
+fn ws<'i, O>(
+    inner: impl Parser<&'i str, O, ErrMode<ContextError>>,
+) -> impl Parser<&'i str, O, ErrMode<ContextError>> {
+    delimited(multispace0, inner, multispace0)
+}
+
+fn sum(input: &mut &str) -> PResult<Expr> {
+    let first = product.parse_next(input)?;
+    let rest: Vec<(char, Expr)> =
+        repeat(0.., (ws(one_of(['+', '-'])), product)).parse_next(input)?;
+    Ok(rest.into_iter().fold(first, |lhs, (op, rhs)| {
+        let op = if op == '+' { Op::Add } else { Op::Sub };
+        Expr::Binary(op, Box::new(lhs), Box::new(rhs))
+    }))
+}
+
+fn product(input: &mut &str) -> PResult<Expr> {
+    let first = unary.parse_next(input)?;
+    let rest: Vec<(char, Expr)> =
+        repeat(0.., (ws(one_of(['*', '/', '%'])), unary)).parse_next(input)?;
+    Ok(rest.into_iter().fold(first, |lhs, (op, rhs)| {
+        let op = match op {
+            '*' => Op::Mul,
+            '/' => Op::Div,
+            _ => Op::Rem,
+        };
+        Expr::Binary(op, Box::new(lhs), Box::new(rhs))
+    }))
+}
+
+fn unary(input: &mut &str) -> PResult<Expr> {
+    alt((
+        preceded(ws('-'), unary).map(|inner| Expr::Neg(Box::new(inner))),
+        ws(atom),
+    ))
+    .parse_next(input)
+}
+
+fn atom(input: &mut &str) -> PResult<Expr> {
+    alt((
+        number_literal,
+        string_literal('"').map(Expr::Str),
+        string_literal('\'').map(Expr::Str),
+        delimited('(', ws(sum), ')'),
+        call_or_path,
+    ))
+    .parse_next(input)
+}
+
+fn number_literal(input: &mut &str) -> PResult<Expr> {
+    let (digits, fraction) = (digit1, opt(preceded('.', digit1))).parse_next(input)?;
+    let literal = match fraction {
+        Some(fraction) => format!("{}.{}", digits, fraction)
+            .parse()
+            .ok()
+            .map(Expr::Float),
+        None => digits.parse().ok().map(Expr::Int),
+    };
+    literal.ok_or_else(|| ErrMode::Cut(ContextError::new()))
+}
+
+fn string_literal<'i>(quote: char) -> impl Parser<&'i str, String, ErrMode<ContextError>> {
+    delimited(
+        quote,
+        repeat(0.., alt((preceded('\\', any), none_of([quote, '\\'])))),
+        quote,
+    )
+}
+
+fn identifier<'i>(input: &mut &'i str) -> PResult<&'i str> {
+    take_while(1.., |c: char| c.is_alphanumeric() || c == '_').parse_next(input)
+}
+
+fn call_or_path(input: &mut &str) -> PResult<Expr> {
+    let name = identifier.parse_next(input)?;
+
+    let args: Option<Vec<Expr>> =
+        opt(delimited(ws('('), separated(0.., ws(sum), ','), ')')).parse_next(input)?;
+    if let Some(args) = args {
+        return Ok(Expr::Call(name.to_string(), args));
+    }
+
+    let rest: Vec<&str> = repeat(0.., preceded('.', identifier)).parse_next(input)?;
+    let mut path = vec![name.to_string()];
+    path.extend(rest.into_iter().map(str::to_string));
+    Ok(Expr::Path(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(input: &str) -> Result<ExprValue> {
+        let lookup = |path: &[String]| match path.join(".").as_str() {
+            "price" => Ok(ExprValue::Float(2.5)),
+            "quantity" => Ok(ExprValue::Int(4)),
+            "customer.first_name" => Ok(ExprValue::Str("Ada".to_string())),
+            "created_at" => Ok(ExprValue::Timestamp(1_700_000_000, 0)),
+            path => bail!("No field {}", path),
+        };
+        Expr::parse(input)?.eval(&lookup, &mut rand::rng())
+    }
+
+    #[test]
+    fn test_arithmetic() -> Result<()> {
+        assert_eq!(eval("price * quantity")?, ExprValue::Float(10.0));
+        assert_eq!(eval("1 + 2 * 3")?, ExprValue::Int(7));
+        assert_eq!(eval("(1 + 2) * 3")?, ExprValue::Int(9));
+        assert_eq!(eval("-quantity % 3")?, ExprValue::Int(-1));
+        assert_eq!(eval("round(price * 3, 1)")?, ExprValue::Float(7.5));
+        assert!(eval("quantity / 0").is_err());
+        let err = eval("abs(-9223372036854775807 - 1)").unwrap_err();
+        assert!(err.to_string().contains("overflow"), "{}", err);
+        let err = eval("duration('20000000000000w')").unwrap_err();
+        assert!(err.to_string().contains("too long"), "{}", err);
+        Ok(())
+    }
+
+    #[test]
+    fn test_strings_and_timestamps() -> Result<()> {
+        assert_eq!(
+            eval("lower(customer.first_name) + '@example.com'")?,
+            ExprValue::Str("ada@example.com".to_string())
+        );
+        assert_eq!(
+            eval("concat(upper(substr(customer.first_name, 0, 1)), \"-\", quantity)")?,
+            ExprValue::Str("A-4".to_string())
+        );
+        assert_eq!(
+            eval("created_at + duration('1h30m')")?,
+            ExprValue::Timestamp(1_700_005_400, 0)
+        );
+        let ExprValue::Timestamp(seconds, _) = eval("created_at + random(0, 60)")? else {
+            panic!("expected a timestamp");
+        };
+        assert!((1_700_000_000..=1_700_000_060).contains(&seconds));
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_expressions() {
+        assert!(Expr::parse("price *").is_err());
+        assert!(Expr::parse("unknown(price)").is_err());
+        assert!(Expr::parse("lower(a, b)").is_err());
+        assert!(eval("missing + 1").is_err());
+    }
+}
@@ -0,0 +1,229 @@
+use anyhow::{Result, anyhow};
+use fake::Fake;
+use rand::{Rng, RngCore};
+
+use crate::locales::{Locale, localized};
+
+/// A generator from the `fake` crate, by dotted name like `address.city_name`
+#[derive(Debug)]
+pub struct Faker {
+    pub name: &'static str,
+    generate: fn(Locale, &mut dyn RngCore) -> String,
+}
+
+impl Faker {
+    pub fn generate<R: Rng + ?Sized>(&self, locale: Locale, mut rng: &mut R) -> String {
+        (self.generate)(locale, &mut rng)
+    }
+}
+
+/// Build the catalog from `name => module::Faker(args)` entries. Fakers that only exist for
+/// one locale name it with `in`, and fakers that don't produce a string give a conversion
+/// for their output.
+macro_rules! catalog {
+    ($(
+        $name:literal => $module:ident::$faker:ident($($arg:expr),*)
+        $(in $locale:ident)? $(=> $convert:expr)?;
+    )*) => {
+        /// Every faker of the `fake` crate, sorted by name
+        pub const FAKERS: &[Faker] = &[$(
+            Faker {
+                name: $name,
+                generate: |locale, rng| {
+                    catalog!(@output
+                        catalog!(@generate locale, $module::$faker($($arg),*), rng $(, $locale)?)
+                        $(, $convert)?
+                    )
+                },
+            },
+        )*];
+    };
+    (@generate $locale:ident, $module:ident::$faker:ident($($arg:expr),*), $rng:ident) => {
+        localized!($locale, $module::$faker($($arg),*), $rng)
+    };
+    (@generate $selected:ident, $module:ident::$faker:ident($($arg:expr),*), $rng:ident, $locale:ident) => {{
+        let _ = $selected;
+        fake::faker::$module::raw::$faker(fake::locales::$locale $(, $arg)*).fake_with_rng($rng)
+    }};
+    (@output $value:expr) => {{
+        let value: String = $value;
+        value
+    }};
+    (@output $value:expr, $convert:expr) => {
+        ($convert)($value)
+    };
+}
+
+catalog! {
+    "address.building_number" => address::BuildingNumber();
+    "address.city_name" => address::CityName();
+    "address.city_prefix" => address::CityPrefix();
+    "address.city_suffix" => address::CitySuffix();
+    "address.country_code" => address::CountryCode();
+    "address.country_name" => address::CountryName();
+    "address.geohash" => address::Geohash(7);
+    "address.latitude" => address::Latitude();
+    "address.longitude" => address::Longitude();
+    "address.post_code" => address::PostCode();
+    "address.secondary_address" => address::SecondaryAddress();
+    "address.secondary_address_type" => address::SecondaryAddressType();
+    "address.state_abbr" => address::StateAbbr();
+    "address.state_name" => address::StateName();
+    "address.street_name" => address::StreetName();
+    "address.street_suffix" => address::StreetSuffix();
+    "address.time_zone" => address::TimeZone();
+    "address.zip_code" => address::ZipCode();
+    "administrative.health_insurance_code" => administrative::HealthInsuranceCode() in FR_FR;
+    "automotive.licence_plate" => automotive::LicencePlate() in FR_FR;
+    "barcode.isbn" => barcode::Isbn();
+    "barcode.isbn10" => barcode::Isbn10();
+    "barcode.isbn13" => barcode::Isbn13();
+    "boolean.boolean" => boolean::Boolean(50) => |b: bool| b.to_string();
+    "chrono.date" => chrono::Date();
+    "chrono.date_time" => chrono::DateTime();
+    "chrono.date_time_after" => chrono::DateTimeAfter(::chrono::Utc::now());
+    "chrono.date_time_before" => chrono::DateTimeBefore(::chrono::Utc::now());
+    "chrono.duration" => chrono::Duration() => |d: ::chrono::Duration| d.to_string();
+    "chrono.time" => chrono::Time();
+    "color.color" => color::Color();
+    "color.hex_color" => color::HexColor();
+    "color.hsl_color" => color::HslColor();
+    "color.hsla_color" => color::HslaColor();
+    "color.rgb_color" => color::RgbColor();
+    "color.rgba_color" => color::RgbaColor();
+    "company.bs" => company::Bs();
+    "company.bs_adj" => company::BsAdj();
+    "company.bs_noun" => company::BsNoun();
+    "company.bs_verb" => company::BsVerb();
+    "company.buzzword" => company::Buzzword();
+    "company.buzzword_middle" => company::BuzzwordMiddle();
+    "company.buzzword_tail" => company::BuzzwordTail();
+    "company.catch_phrase" => company::CatchPhrase();
+    "company.company_name" => company::CompanyName();
+    "company.company_suffix" => company::CompanySuffix();
+    "company.industry" => company::Industry();
+    "company.profession" => company::Profession();
+    "creditcard.credit_card_number" => creditcard::CreditCardNumber();
+    "currency.currency_code" => currency::CurrencyCode();
+    "currency.currency_name" => currency::CurrencyName();
+    "currency.currency_symbol" => currency::CurrencySymbol();
+    "filesystem.dir_path" => filesystem::DirPath();
+    "filesystem.file_extension" => filesystem::FileExtension();
+    "filesystem.file_name" => filesystem::FileName();
+    "filesystem.file_path" => filesystem::FilePath();
+    "filesystem.mime_type" => filesystem::MimeType();
+    "filesystem.semver" => filesystem::Semver();
+    "filesystem.semver_stable" => filesystem::SemverStable();
+    "filesystem.semver_unstable" => filesystem::SemverUnstable();
+    "finance.bic" => finance::Bic();
+    "finance.isin" => finance::Isin();
+    "http.rfc_status_code" => http::RfcStatusCode();
+    "http.valid_status_code" => http::ValidStatusCode();
+    "internet.domain_suffix" => internet::DomainSuffix();
+    "internet.free_email" => internet::FreeEmail();
+    "internet.free_email_provider" => internet::FreeEmailProvider();
+    "internet.ip" => internet::IP();
+    "internet.ipv4" => internet::IPv4();
+    "internet.ipv6" => internet::IPv6();
+    "internet.mac_address" => internet::MACAddress();
+    "internet.password" => internet::Password(8..20);
+    "internet.safe_email" => internet::SafeEmail();
+    "internet.user_agent" => internet::UserAgent();
+    "internet.username" => internet::Username();
+    "job.field" => job::Field();
+    "job.position" => job::Position();
+    "job.seniority" => job::Seniority();
+    "job.title" => job::Title();
+    "lorem.paragraph" => lorem::Paragraph(3..6);
+    "lorem.paragraphs" => lorem::Paragraphs(2..4) => |p: Vec<String>| p.join("\n\n");
+    "lorem.sentence" => lorem::Sentence(4..10);
+    "lorem.sentences" => lorem::Sentences(2..4) => |s: Vec<String>| s.join(" ");
+    "lorem.word" => lorem::Word();
+    "lorem.words" => lorem::Words(2..5) => |w: Vec<String>| w.join(" ");
+    "name.first_name" => name::FirstName();
+    "name.last_name" => name::LastName();
+    "name.name" => name::Name();
+    "name.name_with_title" => name::NameWithTitle();
+    "name.suffix" => name::Suffix();
+    "name.title" => name::Title();
+    "number.digit" => number::Digit();
+    "phone_number.cell_number" => phone_number::CellNumber();
+    "phone_number.phone_number" => phone_number::PhoneNumber();
+}
+
+/// Look up a faker by name
+pub fn find(name: &str) -> Result<&'static Faker> {
+    if let Some(faker) = FAKERS.iter().find(|faker| faker.name == name) {
+        return Ok(faker);
+    }
+
+    // Suggest fakers with the same last part, e.g. `address.city_name` for `city_name`
+    let last = name.rsplit('.').next().unwrap_or(name);
+    let similar: Vec<&str> = FAKERS
+        .iter()
+        .map(|faker| faker.name)
+        .filter(|candidate| candidate.rsplit('.').next() == Some(last))
+        .collect();
+    match similar.as_slice() {
+        [] => Err(anyhow!(
+            "Unknown faker {:?}, run `proto-faker fakers` to list them",
+            name
+        )),
+        similar => Err(anyhow!(
+            "Unknown faker {:?}, did you mean {}?",
+            name,
+            similar.join(" or ")
+        )),
+    }
+}
+
+/// Print every faker whose name contains `filter`, with a sample value in `locale`
+pub fn print_catalog(filter: Option<&str>, locale: Locale) {
+    let mut rng = rand::rng();
+    for faker in FAKERS {
+        if filter.is_some_and(|filter| !faker.name.contains(filter)) {
+            continue;
+        }
+        let sample = faker.generate(locale, &mut rng).replace('\n', " ");
+        let sample = match sample.char_indices().nth(60) {
+            Some((end, _)) => format!("{}...", &sample[..end]),
+            None => sample,
+        };
+        println!("{:<38} {}", faker.name, sample);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catalog() -> Result<()> {
+        assert!(FAKERS.windows(2).all(|w| w[0].name < w[1].name));
+
+        let mut rng = rand::rng();
+        for faker in FAKERS {
+            assert!(
+                !faker.generate(Locale::En, &mut rng).is_empty(),
+                "{}",
+                faker.name
+            );
+            assert!(
+                !faker.generate(Locale::JaJp, &mut rng).is_empty(),
+                "{}",
+                faker.name
+            );
+        }
+
+        let city = find("address.city_name")?.generate(Locale::En, &mut rng);
+        assert!(!city.is_empty());
+        let name = find("name.name")?.generate(Locale::ZhCn, &mut rng);
+        assert!(!name.is_ascii(), "{}", name);
+
+        let err = find("city_name").err().unwrap();
+        assert!(err.to_string().contains("address.city_name"), "{}", err);
+        assert!(find("address.nowhere").is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,265 @@
+use anyhow::{Context, Result, anyhow, bail};
+use prost_reflect::{FieldDescriptor, Kind};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::option_parser::{self, parse_options};
+
+/// Options that choose how a value is generated. Fields with any of them (from comments or
+/// `buf.validate` rules) are left alone by inference.
+pub const GENERATOR_OPTIONS: &[&str] = &[
+    "pool",
+    "pattern",
+    "template",
+    "words",
+    "fake",
+    "string",
+    "sequence",
+    "expr",
+    "values",
+    "weights",
+    "distribution",
+    "timestamp",
+    "bytes",
+    "bytes_of",
+    "bytes_of_field",
+    "bytes_of_types",
+];
+
+const BUILTIN_RULES: &str = include_str!("inference.toml");
+
+/// An inference rules file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    /// Whether the built-in rules apply after the file's rules
+    #[serde(default = "yes")]
+    defaults: bool,
+    #[serde(default)]
+    rules: Vec<RuleSpec>,
+}
+
+fn yes() -> bool {
+    true
+}
+
+impl RulesFile {
+    fn rules(&self) -> Result<Vec<Rule>> {
+        self.rules
+            .iter()
+            .enumerate()
+            .map(|(i, spec)| Rule::new(spec).with_context(|| format!("Rule {}", i + 1)))
+            .collect()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleSpec {
+    names: Vec<String>,
+    kinds: Vec<String>,
+    options: String,
+}
+
+/// Options for unannotated fields whose name and kind match
+#[derive(Debug)]
+struct Rule {
+    /// Lowercase name patterns, where `*` matches anything
+    names: Vec<String>,
+    kinds: Vec<String>,
+    options: HashMap<String, option_parser::Value>,
+}
+
+/// Rules that infer generator options from field names and kinds. The first matching rule
+/// applies.
+#[derive(Debug)]
+pub struct Inference {
+    rules: Vec<Rule>,
+}
+
+impl Default for Inference {
+    fn default() -> Self {
+        let file: RulesFile = toml::from_str(BUILTIN_RULES).expect("built-in rules are valid TOML");
+        Inference {
+            rules: file.rules().expect("built-in inference rules are valid"),
+        }
+    }
+}
+
+impl Inference {
+    /// Load rules from a TOML file. They're matched before the built-in rules, which can be
+    /// turned off with `defaults = false`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Can't read inference rules {}", path.display()))?;
+        Inference::parse(&text).with_context(|| format!("In {}", path.display()))
+    }
+
+    pub(crate) fn parse(text: &str) -> Result<Self> {
+        let file: RulesFile = toml::from_str(text)?;
+        let mut rules = file.rules()?;
+        if file.defaults {
+            rules.extend(Inference::default().rules);
+        }
+
+        Ok(Inference { rules })
+    }
+
+    /// The options inferred for a field, if a rule matches it
+    pub fn options(
+        &self,
+        field: &FieldDescriptor,
+    ) -> Option<&HashMap<String, option_parser::Value>> {
+        let name = field.name().to_lowercase();
+        let kind = kind_name(&field.kind());
+
+        self.rules
+            .iter()
+            .find(|rule| {
+                rule.kinds.iter().any(|k| kind_matches(k, kind))
+                    && rule.names.iter().any(|pattern| glob(pattern, &name))
+            })
+            .map(|rule| &rule.options)
+    }
+}
+
+impl Rule {
+    fn new(spec: &RuleSpec) -> Result<Self> {
+        if spec.names.is_empty() || spec.kinds.is_empty() {
+            bail!("names and kinds can't be empty");
+        }
+        for kind in &spec.kinds {
+            if !KINDS.contains(&kind.as_str()) {
+                bail!(
+                    "Unknown kind {:?}, expected one of {}",
+                    kind,
+                    KINDS.join(", ")
+                );
+            }
+        }
+        let options = parse_options(&spec.options);
+        if options.is_empty() {
+            return Err(anyhow!("No options in {:?}", spec.options));
+        }
+
+        Ok(Rule {
+            names: spec.names.iter().map(|name| name.to_lowercase()).collect(),
+            kinds: spec.kinds.clone(),
+            options,
+        })
+    }
+}
+
+/// Kinds a rule can match: proto scalar types and groups of them
+const KINDS: &[&str] = &[
+    "string", "bytes", "bool", "int32", "sint32", "sfixed32", "int64", "sint64", "sfixed64",
+    "uint32", "fixed32", "uint64", "fixed64", "float", "double", "integer", "decimal", "number",
+];
+
+fn kind_name(kind: &Kind) -> &'static str {
+    match kind {
+        Kind::String => "string",
+        Kind::Bytes => "bytes",
+        Kind::Bool => "bool",
+        Kind::Int32 => "int32",
+        Kind::Sint32 => "sint32",
+        Kind::Sfixed32 => "sfixed32",
+        Kind::Int64 => "int64",
+        Kind::Sint64 => "sint64",
+        Kind::Sfixed64 => "sfixed64",
+        Kind::Uint32 => "uint32",
+        Kind::Fixed32 => "fixed32",
+        Kind::Uint64 => "uint64",
+        Kind::Fixed64 => "fixed64",
+        Kind::Float => "float",
+        Kind::Double => "double",
+        Kind::Enum(_) => "enum",
+        Kind::Message(_) => "message",
+    }
+}
+
+fn kind_matches(rule_kind: &str, kind: &str) -> bool {
+    let integer = matches!(
+        kind,
+        "int32"
+            | "sint32"
+            | "sfixed32"
+            | "int64"
+            | "sint64"
+            | "sfixed64"
+            | "uint32"
+            | "fixed32"
+            | "uint64"
+            | "fixed64"
+    );
+    let decimal = matches!(kind, "float" | "double");
+
+    match rule_kind {
+        "integer" => integer,
+        "decimal" => decimal,
+        "number" => integer || decimal,
+        rule_kind => rule_kind == kind,
+    }
+}
+
+/// Match a name against a pattern where `*` matches any run of characters
+fn glob(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((prefix, rest)) => {
+            let Some(name) = name.strip_prefix(prefix) else {
+                return false;
+            };
+            (0..=name.len())
+                .filter(|&i| name.is_char_boundary(i))
+                .any(|i| glob(rest, &name[i..]))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob() {
+        assert!(glob("city", "city"));
+        assert!(!glob("city", "city_name"));
+        assert!(glob("*_at", "created_at"));
+        assert!(!glob("*_at", "created_by"));
+        assert!(glob("*name*", "name"));
+        assert!(glob("*name*", "display_name_short"));
+        assert!(glob("address_line*", "address_line2"));
+        assert!(glob("a*b*c", "a_b_c"));
+        assert!(!glob("a*b*c", "a_c"));
+    }
+
+    #[test]
+    fn test_rules_file() -> Result<()> {
+        let inference = Inference::parse(
+            r#"
+            [[rules]]
+            names = ["city"]
+            kinds = ["string"]
+            options = "pool=cities"
+            "#,
+        )?;
+        assert_eq!(inference.rules.len(), Inference::default().rules.len() + 1);
+        assert_eq!(
+            inference.rules[0].options.get("pool"),
+            Some(&option_parser::Value::Str("cities".to_string()))
+        );
+
+        let inference = Inference::parse("defaults = false")?;
+        assert!(inference.rules.is_empty());
+
+        let bad_kind = "[[rules]]\nnames = [\"x\"]\nkinds = [\"text\"]\noptions = \"min=1\"";
+        assert!(Inference::parse(bad_kind).is_err());
+        let no_options = "[[rules]]\nnames = [\"x\"]\nkinds = [\"string\"]\noptions = \"\"";
+        assert!(Inference::parse(no_options).is_err());
+        assert!(Inference::parse("[[rule]]").is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,196 @@
+use anyhow::{Result, anyhow, bail};
+use rand::Rng;
+
+/// A locale with data in the `fake` crate
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Locale {
+    En,
+    FrFr,
+    DeDe,
+    ItIt,
+    JaJp,
+    ZhCn,
+    ZhTw,
+    PtBr,
+    PtPt,
+    ArSa,
+}
+
+/// Every locale with its canonical name. A language alone means the first locale of it.
+const LOCALES: &[(Locale, &str)] = &[
+    (Locale::En, "en"),
+    (Locale::FrFr, "fr_FR"),
+    (Locale::DeDe, "de_DE"),
+    (Locale::ItIt, "it_IT"),
+    (Locale::JaJp, "ja_JP"),
+    (Locale::ZhCn, "zh_CN"),
+    (Locale::ZhTw, "zh_TW"),
+    (Locale::PtBr, "pt_BR"),
+    (Locale::PtPt, "pt_PT"),
+    (Locale::ArSa, "ar_SA"),
+];
+
+impl Locale {
+    /// Parse a locale like `de_DE`, `de-DE` or just `de`. Any English locale (`en_US`,
+    /// `en_GB`) is `en`, since the `fake` crate has one.
+    pub fn parse(input: &str) -> Result<Self> {
+        let normalized = input.trim().replace('-', "_").to_lowercase();
+        let language = normalized.split('_').next().unwrap_or_default();
+
+        LOCALES
+            .iter()
+            .find(|(_, name)| name.to_lowercase() == normalized)
+            .or_else(|| match normalized.contains('_') && language != "en" {
+                true => None,
+                false => LOCALES
+                    .iter()
+                    .find(|(_, name)| name.split('_').next() == Some(language)),
+            })
+            .map(|(locale, _)| *locale)
+            .ok_or_else(|| {
+                let names: Vec<&str> = LOCALES.iter().map(|(_, name)| *name).collect();
+                anyhow!(
+                    "Unknown locale {:?}, expected one of {}",
+                    input,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// Locales with relative weights, from `--locale fr_FR` or `--locale en:0.6,de:0.4`
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocaleMix(Vec<(Locale, f64)>);
+
+impl Default for LocaleMix {
+    fn default() -> Self {
+        LocaleMix(vec![(Locale::En, 1.0)])
+    }
+}
+
+impl LocaleMix {
+    pub fn parse(input: &str) -> Result<Self> {
+        let mut locales = Vec::new();
+
+        for part in input.split(',') {
+            let (locale, weight) = match part.split_once(':') {
+                Some((locale, weight)) => {
+                    let weight: f64 = weight.trim().parse().map_err(|_| {
+                        anyhow!("Invalid weight {:?} for locale {}", weight, locale)
+                    })?;
+                    (locale, weight)
+                }
+                None => (part, 1.0),
+            };
+            if !weight.is_finite() || weight < 0.0 {
+                bail!(
+                    "Locale weights must be finite and not negative, got {} for {}",
+                    weight,
+                    locale
+                );
+            }
+            locales.push((Locale::parse(locale)?, weight));
+        }
+
+        if locales.iter().all(|(_, weight)| *weight == 0.0) {
+            bail!("At least one locale needs a weight above 0");
+        }
+        if !locales
+            .iter()
+            .map(|(_, weight)| weight)
+            .sum::<f64>()
+            .is_finite()
+        {
+            bail!("Locale weights are too large, their sum must be finite");
+        }
+        Ok(LocaleMix(locales))
+    }
+
+    /// Pick a locale by weight
+    pub fn pick<R: Rng + ?Sized>(&self, rng: &mut R) -> Locale {
+        if let [(locale, _)] = self.0.as_slice() {
+            return *locale;
+        }
+
+        let total: f64 = self.0.iter().map(|(_, weight)| weight).sum();
+        let mut target = rng.random_range(0.0..total);
+        for (locale, weight) in &self.0 {
+            if target < *weight {
+                return *locale;
+            }
+            target -= weight;
+        }
+        self.0.last().unwrap().0
+    }
+}
+
+/// Generate with a `fake` faker in a runtime locale: `localized!(locale, module::Faker(args), rng)`
+macro_rules! localized {
+    ($locale:expr, $module:ident::$faker:ident($($arg:expr),*), $rng:expr) => {{
+        use fake::Fake;
+        use fake::faker::$module::raw::$faker;
+        use fake::locales::*;
+        use $crate::locales::Locale;
+
+        match $locale {
+            Locale::En => $faker(EN $(, $arg)*).fake_with_rng($rng),
+            Locale::FrFr => $faker(FR_FR $(, $arg)*).fake_with_rng($rng),
+            Locale::DeDe => $faker(DE_DE $(, $arg)*).fake_with_rng($rng),
+            Locale::ItIt => $faker(IT_IT $(, $arg)*).fake_with_rng($rng),
+            Locale::JaJp => $faker(JA_JP $(, $arg)*).fake_with_rng($rng),
+            Locale::ZhCn => $faker(ZH_CN $(, $arg)*).fake_with_rng($rng),
+            Locale::ZhTw => $faker(ZH_TW $(, $arg)*).fake_with_rng($rng),
+            Locale::PtBr => $faker(PT_BR $(, $arg)*).fake_with_rng($rng),
+            Locale::PtPt => $faker(PT_PT $(, $arg)*).fake_with_rng($rng),
+            Locale::ArSa => $faker(AR_SA $(, $arg)*).fake_with_rng($rng),
+        }
+    }};
+}
+pub(crate) use localized;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_locales() -> Result<()> {
+        assert_eq!(Locale::parse("fr_FR")?, Locale::FrFr);
+        assert_eq!(Locale::parse("de-de")?, Locale::DeDe);
+        assert_eq!(Locale::parse("ja")?, Locale::JaJp);
+        assert_eq!(Locale::parse("pt")?, Locale::PtBr);
+        assert_eq!(Locale::parse("pt_PT")?, Locale::PtPt);
+        assert_eq!(Locale::parse("en_US")?, Locale::En);
+        assert!(Locale::parse("de_AT").is_err());
+        assert!(Locale::parse("xx").is_err());
+        assert!(Locale::parse("z").is_err());
+        assert!(Locale::parse("").is_err());
+
+        assert_eq!(
+            LocaleMix::parse("zh_CN")?,
+            LocaleMix(vec![(Locale::ZhCn, 1.0)])
+        );
+        assert_eq!(
+            LocaleMix::parse("en:0.6,de:0.4")?,
+            LocaleMix(vec![(Locale::En, 0.6), (Locale::DeDe, 0.4)])
+        );
+        assert!(LocaleMix::parse("en:-1").is_err());
+        assert!(LocaleMix::parse("en:0").is_err());
+        assert!(LocaleMix::parse("en:x").is_err());
+        assert!(LocaleMix::parse("en:inf").is_err());
+        assert!(LocaleMix::parse("en:1e308,de:1e308").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pick() -> Result<()> {
+        let mix = LocaleMix::parse("en:3,fr:1,de:0")?;
+        let mut rng = rand::rng();
+        let picks: Vec<Locale> = (0..4000).map(|_| mix.pick(&mut rng)).collect();
+        let en = picks.iter().filter(|&&l| l == Locale::En).count();
+        assert!((2700..3300).contains(&en), "{}", en);
+        assert!(!picks.contains(&Locale::DeDe));
+
+        Ok(())
+    }
+}
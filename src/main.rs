@@ -1,21 +1,38 @@
+mod binary;
 mod distribution;
+mod enums;
+mod expr;
+mod fakers;
+mod inference;
+mod locales;
+mod oneofs;
 mod option_parser;
+mod overlay;
+mod pattern;
+mod pools;
 mod proto_faker;
 mod proto_loader;
+mod sequence;
+mod template;
+mod validate;
 
 use anyhow::{Context, Result, anyhow};
 use clap::{Parser, Subcommand};
 use indicatif::{ProgressBar, ProgressStyle};
+use inference::Inference;
+use locales::{Locale, LocaleMix};
+use overlay::Overlay;
 use prost_reflect::prost::Message;
 use prost_reflect::{DynamicMessage, MessageDescriptor, ReflectMessage, Value};
 use proto_faker::ProtoFaker;
 use proto_loader::ProtoLoader;
-use rayon::iter::ParallelIterator;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use rdkafka::config::ClientConfig;
 use rdkafka::producer::{FutureProducer, FutureRecord};
 use schema_registry_converter::async_impl::proto_raw::ProtoRawEncoder;
 use schema_registry_converter::async_impl::schema_registry::{self, SrSettings};
 use schema_registry_converter::schema_registry_common::{SubjectNameStrategy, SuppliedSchema};
+use std::cell::RefCell;
 use std::fs;
 use std::io::{Seek, Write};
 use std::path::PathBuf;
@@ -49,13 +66,49 @@ struct Common {
     /// Kafka key field (default: 'id')
     #[arg(short, long)]
     key: Option<String>,
+
+    /// Fraction (0.0 - 1.0) of fields with buf.validate rules to deliberately make invalid
+    #[arg(long, default_value_t = 0.0, value_parser = parse_fraction)]
+    violation_rate: f64,
+
+    /// Fraction (0.0 - 1.0) of optional fields to leave unset, unless overridden by `presence`
+    #[arg(long, default_value_t = 0.0, value_parser = parse_fraction)]
+    sparsity: f64,
+
+    /// Seed for reproducible runs; sequences are then numbered by message index
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Locale of fake data, or a weighted mix picked per message (e.g. `en:0.6,de:0.4`)
+    #[arg(long, value_parser = LocaleMix::parse)]
+    locale: Option<LocaleMix>,
+
+    /// TOML file of rules inferring options for unannotated fields from their names and types
+    #[arg(long)]
+    inference: Option<PathBuf>,
+
+    /// TOML file of generator options by field path, for schemas that can't carry comments
+    #[arg(long)]
+    overlay: Option<PathBuf>,
+
+    /// Override a field's options for this run: `PATH=OPTIONS` or `PATH.KEY=VALUE`, with
+    /// paths relative to the message type or fully qualified (repeatable)
+    #[arg(long = "set", value_name = "PATH=OPTIONS")]
+    set: Vec<String>,
+}
+
+fn parse_fraction(input: &str) -> Result<f64> {
+    let value: f64 = input.parse()?;
+    if !(0.0..=1.0).contains(&value) {
+        return Err(anyhow!("{} is not between 0.0 and 1.0", value));
+    }
+    Ok(value)
 }
 
 #[derive(Clone, Debug, PartialEq)]
 struct PoolConfig {
     name: String,
-    items: usize,
-    value: option_parser::ValueType,
+    source: pools::PoolSource,
 }
 
 #[derive(Subcommand, Debug, PartialEq)]
@@ -88,6 +141,15 @@ enum Commands {
         #[arg(short, long)]
         output: PathBuf,
     },
+    /// List the fakers available to the `fake` option, with sample output
+    Fakers {
+        /// Only list fakers whose name contains this text
+        filter: Option<String>,
+
+        /// Locale of the samples
+        #[arg(long, default_value = "en", value_parser = Locale::parse)]
+        locale: Locale,
+    },
 }
 
 #[tokio::main]
@@ -97,6 +159,10 @@ async fn main() -> Result<()> {
         Commands::Print { common } => common,
         Commands::Publish { common, .. } => common,
         Commands::Write { common, .. } => common,
+        Commands::Fakers { filter, locale } => {
+            fakers::print_catalog(filter.as_deref(), *locale);
+            return Ok(());
+        }
     };
 
     println!("Loading proto file: {}", common.proto_file.display());
@@ -111,21 +177,44 @@ async fn main() -> Result<()> {
     let message_descriptor = loader.get_message_descriptor(&common.message_type).unwrap();
     println!("Found message type: {}", message_descriptor.full_name());
 
+    let pools = common.pools.clone().unwrap_or(vec![]);
+    let mut faker = ProtoFaker::new(pools)?
+        .with_violation_rate(common.violation_rate)
+        .with_sparsity(common.sparsity);
+    if let Some(seed) = common.seed {
+        faker = faker.with_seed(seed);
+    }
+    if let Some(locales) = common.locale.clone() {
+        faker = faker.with_locales(locales);
+    }
+    if let Some(path) = &common.inference {
+        faker = faker.with_inference(Inference::load(path)?);
+    }
+    let mut overlay = match &common.overlay {
+        Some(path) => Overlay::load(path, &loader)?,
+        None => Overlay::default(),
+    };
+    for spec in &common.set {
+        overlay.set(&loader, &message_descriptor, spec)?;
+    }
+    faker = faker.with_overlay(overlay);
+    faker.prepare(&loader, &message_descriptor)?;
+
     let (tx, messages) = std::sync::mpsc::sync_channel(100);
     let loader1 = loader.clone();
-    let pools = common.pools.clone().unwrap_or(vec![]);
     let message_descriptor1 = message_descriptor.clone();
     let count = common.count;
     std::thread::spawn(move || {
-        let faker = ProtoFaker::new(pools);
-
-        rayon::iter::repeatn((), count).for_each(|_| {
-            let msg = faker
-                .generate_dynamic(&loader1, &message_descriptor1)
-                .unwrap();
+        let generated = (0..count).into_par_iter().try_for_each(|index| {
+            let msg = faker.generate_indexed(&loader1, &message_descriptor1, index as u64)?;
 
-            tx.send(msg).unwrap();
+            // The receiver only hangs up once it has stopped at an error
+            tx.send(Ok(msg))
+                .map_err(|_| anyhow!("Message consumer stopped"))
         });
+        if let Err(e) = generated {
+            let _ = tx.send(Err(e));
+        }
     });
     let bar = ProgressBar::new(common.count as u64);
     bar.set_style(
@@ -135,7 +224,19 @@ async fn main() -> Result<()> {
         .unwrap()
         .progress_chars("##-"),
     );
-    let messages = messages.into_iter().inspect(|_| bar.inc(1));
+    // Generation stops at the first error, which is returned once the messages before it
+    // are handled
+    let error = RefCell::new(None);
+    let messages = messages
+        .into_iter()
+        .map_while(|msg| match msg {
+            Ok(msg) => Some(msg),
+            Err(e) => {
+                error.replace(Some(e));
+                None
+            }
+        })
+        .inspect(|_| bar.inc(1));
 
     match args.cmd {
         Commands::Publish {
@@ -206,15 +307,22 @@ async fn main() -> Result<()> {
             let key_field = common.key.as_deref().unwrap_or("id");
 
             write_to_zstd_file(&loader, key_field, messages, &message_descriptor, &output)?;
+            if let Some(e) = error.take() {
+                return Err(e);
+            }
             println!(
                 "Successfully wrote {} messages to {}",
                 common.count,
                 output.display()
             );
         }
+        Commands::Fakers { .. } => unreachable!("fakers returns before generating"),
     }
 
-    Ok(())
+    match error.take() {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
 }
 
 async fn publish_to_kafka(
@@ -365,16 +473,13 @@ fn print_field_value(
             }
         }
         Value::EnumNumber(value) => {
-            if let Some(field) = message_descriptor.fields().find(|f| f.name() == name) {
-                // Check if this field is an enum type
-                if let prost_reflect::Kind::Enum(enum_descriptor) = field.kind() {
-                    if let Some(enum_value) =
-                        enum_descriptor.values().find(|v| v.number() == *value)
-                    {
-                        println!("{}{}: {} ({})", indent_str, name, enum_value.name(), value);
-                        return;
-                    }
-                }
+            // Check if this field is an enum type
+            if let Some(field) = message_descriptor.fields().find(|f| f.name() == name)
+                && let prost_reflect::Kind::Enum(enum_descriptor) = field.kind()
+                && let Some(enum_value) = enum_descriptor.values().find(|v| v.number() == *value)
+            {
+                println!("{}{}: {} ({})", indent_str, name, enum_value.name(), value);
+                return;
             }
             // Fallback if we can't find the enum descriptor or value
             println!("{}{}: ENUM_VALUE ({})", indent_str, name, value);
@@ -0,0 +1,99 @@
+use anyhow::{Result, anyhow, bail};
+use prost_reflect::{FieldDescriptor, OneofDescriptor};
+use rand::Rng;
+use rand::distr::Distribution;
+use rand::distr::weighted::WeightedIndex;
+use std::collections::HashMap;
+
+use crate::option_parser;
+
+/// How the member of a oneof is picked, built from the options on its declaration:
+/// `weights=[field:w,...]`, `none=P` and `mode=cycle`.
+#[derive(Debug, Clone)]
+pub struct OneofChoices {
+    fields: Vec<FieldDescriptor>,
+    weights: Option<WeightedIndex<f64>>,
+    none: f64,
+    cycle: bool,
+}
+
+impl OneofChoices {
+    pub fn new(
+        oneof: &OneofDescriptor,
+        options: &HashMap<String, option_parser::Value>,
+    ) -> Result<Self> {
+        let mut fields: Vec<FieldDescriptor> = oneof.fields().collect();
+
+        let weights = match options.get("weights") {
+            Some(option_parser::Value::ListWeighted(items)) => {
+                let mut weighted_fields = Vec::new();
+                let mut weights = Vec::new();
+                for (name, weight) in items {
+                    let field = fields.iter().find(|f| f.name() == name).ok_or_else(|| {
+                        anyhow!("Oneof {} has no field {}", oneof.full_name(), name)
+                    })?;
+                    weighted_fields.push(field.clone());
+                    weights.push(*weight);
+                }
+                fields = weighted_fields;
+                Some(WeightedIndex::new(weights).map_err(|e| {
+                    anyhow!("Invalid weights for oneof {}: {}", oneof.full_name(), e)
+                })?)
+            }
+            Some(_) => bail!("weights must be a list of field:weight pairs"),
+            None => None,
+        };
+
+        let none = match options.get("none") {
+            Some(option_parser::Value::Float(f)) => *f,
+            Some(option_parser::Value::Int(i)) => *i as f64,
+            Some(_) => bail!("none must be a probability between 0 and 1"),
+            None => 0.0,
+        };
+        if !(0.0..=1.0).contains(&none) {
+            bail!("none must be a probability between 0 and 1, got {}", none);
+        }
+
+        let cycle = match options.get("mode") {
+            Some(option_parser::Value::Str(mode)) if mode == "cycle" => true,
+            Some(option_parser::Value::Str(mode)) if mode == "random" => false,
+            Some(mode) => bail!("Unknown oneof mode {:?}, expected cycle or random", mode),
+            None => false,
+        };
+
+        Ok(OneofChoices {
+            fields,
+            weights,
+            none,
+            cycle,
+        })
+    }
+
+    /// Pick the member to set, or `None` to leave the oneof empty.
+    ///
+    /// In cycle mode `turn` is called to get how many times this oneof has been generated
+    /// so far; an empty oneof takes a turn of its own when `none` is set.
+    pub fn select<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        turn: impl FnOnce() -> usize,
+    ) -> Option<&FieldDescriptor> {
+        if self.fields.is_empty() {
+            return None;
+        }
+
+        if self.cycle {
+            let slots = self.fields.len() + usize::from(self.none > 0.0);
+            return self.fields.get(turn() % slots);
+        }
+
+        if self.none > 0.0 && rng.random_bool(self.none) {
+            return None;
+        }
+
+        match &self.weights {
+            Some(weights) => Some(&self.fields[weights.sample(rng)]),
+            None => Some(&self.fields[rng.random_range(0..self.fields.len())]),
+        }
+    }
+}
@@ -2,25 +2,52 @@ use std::collections::HashMap;
 use winnow::ascii::{self, Caseless};
 use winnow::error::{AddContext, ContextError, ErrMode};
 use winnow::prelude::*;
-use winnow::token::take_until;
 use winnow::{
-    ascii::{alphanumeric1, digit1},
-    combinator::{alt, delimited, preceded, repeat, separated, separated_pair},
-    token::{one_of, take_while},
+    ascii::digit1,
+    combinator::{alt, delimited, opt, preceded, repeat, separated, separated_pair, terminated},
+    token::{any, one_of, take_while},
 };
 
 use crate::PoolConfig;
+use crate::distribution::Sampler;
+use crate::pools::PoolSource;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Value {
     Int(i64),
+    Float(f64),
     Str(String),
     Bool(bool),
+    /// An integer too large for `Int`, like a `uint64` bound
+    UInt(u64),
     ListInt(Vec<i64>),
     ListStr(Vec<String>),
     ListBool(Vec<bool>),
+    ListWeighted(Vec<(String, f64)>),
     Range(i64, i64),
+    RangeFrom(i64),
     Distribution(Distribution),
+    Skew(Skew),
+}
+
+impl Value {
+    /// An integer as `Int`, or as `UInt` if it's too large, saturating at the ends of both
+    pub fn integer(value: i128) -> Value {
+        match i64::try_from(value) {
+            Ok(value) => Value::Int(value),
+            Err(_) if value < 0 => Value::Int(i64::MIN),
+            Err(_) => Value::UInt(u64::try_from(value).unwrap_or(u64::MAX)),
+        }
+    }
+
+    /// The value of an `Int` or `UInt`
+    pub fn as_i128(&self) -> Option<i128> {
+        match self {
+            Value::Int(value) => Some(i128::from(*value)),
+            Value::UInt(value) => Some(i128::from(*value)),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -42,6 +69,35 @@ pub enum Distribution {
     Normal(f64, f64),
     LogNormal(f64, f64),
     Pareto(f64, f64),
+    /// Rate
+    Exponential(f64),
+    /// Number of ranks and exponent
+    Zipf(f64, f64),
+    /// Mean
+    Poisson(f64),
+    /// Shape and scale
+    Gamma(f64, f64),
+    /// Alpha and beta
+    Beta(f64, f64),
+    /// Min, max and mode
+    Triangular(f64, f64, f64),
+    /// Scale and shape
+    Weibull(f64, f64),
+    /// Probability of success
+    Bernoulli(f64),
+    /// Relative weights of outcomes
+    Categorical(Vec<f64>),
+    /// Path of a CSV file with a histogram or samples
+    Empirical(String),
+}
+
+/// Popularity of pool values by their position in the pool
+#[derive(Debug, PartialEq, Clone)]
+pub enum Skew {
+    /// Zipf's law with this exponent
+    Zipf(f64),
+    /// This fraction of values gets that fraction of picks
+    Hotset(f64, f64),
 }
 
 fn parse_value_type(input: &mut &str) -> winnow::error::ModalResult<ValueType> {
@@ -76,8 +132,15 @@ fn parse_value_type(input: &mut &str) -> winnow::error::ModalResult<ValueType> {
     }
 }
 
+/// An option name: a word, or a dotted path like `sub.lines[].price.min`
 fn key<'i>(input: &mut &'i str) -> winnow::error::ModalResult<&'i str> {
-    alphanumeric1.parse_next(input)
+    let segment = (
+        take_while(1.., |c: char| c.is_alphanumeric() || c == '_'),
+        opt("[]"),
+    );
+    separated::<_, _, (), _, _, _, _>(1.., segment, '.')
+        .take()
+        .parse_next(input)
 }
 
 fn quoted_string(input: &mut &str) -> winnow::error::ModalResult<String> {
@@ -89,14 +152,13 @@ fn quoted_string(input: &mut &str) -> winnow::error::ModalResult<String> {
                 take_while(1.., |c| c != '"' && c != '\\').map(|s: &str| s.to_string()),
                 preceded(
                     '\\',
-                    one_of(['\"', 'n', '\\'])
-                        .map(|c| match c {
-                            'n' => '\n',
-                            '"' => '"',
-                            '\\' => '\\',
-                            _ => c,
-                        })
-                        .map(|c| c.to_string()),
+                    any.map(|c| match c {
+                        'n' => "\n".to_string(),
+                        '"' => "\"".to_string(),
+                        '\\' => "\\".to_string(),
+                        // Keep unknown escapes (e.g. regex classes like `\d`) verbatim
+                        _ => format!("\\{}", c),
+                    }),
                 ),
             )),
         )
@@ -107,7 +169,21 @@ fn quoted_string(input: &mut &str) -> winnow::error::ModalResult<String> {
 }
 
 fn parse_int(input: &mut &str) -> winnow::error::ModalResult<i64> {
-    digit1.parse_to().parse_next(input)
+    (opt('-'), digit1).take().parse_to().parse_next(input)
+}
+
+/// A float literal with a mandatory fractional part, so `1..5` still parses as a range
+fn parse_float(input: &mut &str) -> winnow::error::ModalResult<f64> {
+    (
+        opt('-'),
+        digit1,
+        '.',
+        digit1,
+        opt((one_of(['e', 'E']), opt(one_of(['+', '-'])), digit1)),
+    )
+        .take()
+        .parse_to()
+        .parse_next(input)
 }
 
 fn parse_range(input: &mut &str) -> winnow::error::ModalResult<Value> {
@@ -122,6 +198,11 @@ fn parse_range(input: &mut &str) -> winnow::error::ModalResult<Value> {
             *input = input_copy;
             return Ok(Value::Range(start, end));
         }
+        // An open range like `1000..`
+        if input_copy.is_empty() || input_copy.starts_with(char::is_whitespace) {
+            *input = input_copy;
+            return Ok(Value::RangeFrom(start));
+        }
     }
 
     Err(ErrMode::Backtrack(ContextError::new().add_context(
@@ -135,21 +216,95 @@ fn parse_f64(input: &mut &str) -> winnow::error::ModalResult<f64> {
     ascii::float.parse_next(input)
 }
 
+/// `name(a,b)` with two numbers
+fn two_params<'i>(name: &'static str) -> impl Parser<&'i str, (f64, f64), ErrMode<ContextError>> {
+    (Caseless(name), "(", parse_f64, ",", parse_f64, ")").map(|(_, _, a, _, b, _)| (a, b))
+}
+
+/// `name(a)` with one number
+fn one_param<'i>(name: &'static str) -> impl Parser<&'i str, f64, ErrMode<ContextError>> {
+    (Caseless(name), "(", parse_f64, ")").map(|(_, _, a, _)| a)
+}
+
+/// A distribution with valid parameters, so `normal(0,-1)` doesn't parse as one
 fn parse_distribution(input: &mut &str) -> winnow::error::ModalResult<Distribution> {
     let distribution = alt((
         Caseless("uniform").map(|_| Distribution::Uniform),
-        (Caseless("pareto"), "(", parse_f64, ",", parse_f64, ")")
-            .map(|(_, _, a, _, b, _)| Distribution::Pareto(a, b)),
-        (Caseless("normal"), "(", parse_f64, ",", parse_f64, ")")
-            .map(|(_, _, a, _, b, _)| Distribution::Normal(a, b)),
-        (Caseless("log_normal"), "(", parse_f64, ",", parse_f64, ")")
-            .map(|(_, _, a, _, b, _)| Distribution::LogNormal(a, b)),
+        two_params("pareto").map(|(a, b)| Distribution::Pareto(a, b)),
+        two_params("normal").map(|(a, b)| Distribution::Normal(a, b)),
+        two_params("log_normal").map(|(a, b)| Distribution::LogNormal(a, b)),
+        one_param("exponential").map(Distribution::Exponential),
+        two_params("zipf").map(|(n, s)| Distribution::Zipf(n, s)),
+        one_param("poisson").map(Distribution::Poisson),
+        two_params("gamma").map(|(a, b)| Distribution::Gamma(a, b)),
+        two_params("beta").map(|(a, b)| Distribution::Beta(a, b)),
+        (
+            Caseless("triangular"),
+            "(",
+            parse_f64,
+            ",",
+            parse_f64,
+            ",",
+            parse_f64,
+            ")",
+        )
+            .map(|(_, _, min, _, max, _, mode, _)| Distribution::Triangular(min, max, mode)),
+        two_params("weibull").map(|(a, b)| Distribution::Weibull(a, b)),
+        one_param("bernoulli").map(Distribution::Bernoulli),
+        (
+            Caseless("categorical"),
+            "(",
+            delimited("[", separated(1.., parse_f64, ","), "]"),
+            ")",
+        )
+            .map(|(_, _, weights, _)| Distribution::Categorical(weights)),
+        (
+            Caseless("empirical"),
+            "(",
+            // Paths with spaces or parentheses are quoted
+            alt((
+                quoted_string,
+                take_while(1.., |c: char| c != ')' && !c.is_whitespace()).map(str::to_string),
+            )),
+            ")",
+        )
+            .map(|(_, _, path, _)| Distribution::Empirical(path)),
     ))
+    // Files are read by `ProtoFaker::prepare`, which reports what's wrong with them
+    .verify(|distribution| {
+        matches!(distribution, Distribution::Empirical(_)) || Sampler::new(distribution).is_ok()
+    })
     .parse_next(input)?;
 
     Ok(distribution)
 }
 
+/// A fraction, either as a decimal (`0.8`) or a percentage (`80%`)
+fn parse_fraction(input: &mut &str) -> winnow::error::ModalResult<f64> {
+    (parse_f64, opt('%'))
+        .map(|(value, percent)| match percent {
+            Some(_) => value / 100.0,
+            None => value,
+        })
+        .parse_next(input)
+}
+
+fn parse_skew(input: &mut &str) -> winnow::error::ModalResult<Skew> {
+    alt((
+        (Caseless("zipf"), "(", parse_f64, ")").map(|(_, _, s, _)| Skew::Zipf(s)),
+        (
+            Caseless("hotset"),
+            "(",
+            parse_fraction,
+            ",",
+            parse_fraction,
+            ")",
+        )
+            .map(|(_, _, keys, _, picks, _)| Skew::Hotset(keys, picks)),
+    ))
+    .parse_next(input)
+}
+
 fn parse_bool(input: &mut &str) -> winnow::error::ModalResult<bool> {
     alt(("true".value(true), "false".value(false))).parse_next(input)
 }
@@ -162,6 +317,24 @@ fn parse_bare_str(input: &mut &str) -> winnow::error::ModalResult<String> {
     .parse_next(input)
 }
 
+/// A bare list item such as an enum value or field name
+fn identifier<'i>(input: &mut &'i str) -> winnow::error::ModalResult<&'i str> {
+    take_while(1.., |c: char| {
+        c.is_alphanumeric() || c == '_' || c == '.' || c == '-'
+    })
+    .parse_next(input)
+}
+
+/// A `name:weight` list item
+fn weighted_item(input: &mut &str) -> winnow::error::ModalResult<(String, f64)> {
+    separated_pair(
+        alt((quoted_string, identifier.map(str::to_string))),
+        ":",
+        alt((parse_float, parse_int.map(|i| i as f64))),
+    )
+    .parse_next(input)
+}
+
 fn list_value(input: &mut &str) -> winnow::error::ModalResult<Value> {
     delimited(
         "[",
@@ -169,6 +342,8 @@ fn list_value(input: &mut &str) -> winnow::error::ModalResult<Value> {
             separated(1.., parse_bool, ",").map(Value::ListBool),
             separated(1.., parse_int, ",").map(Value::ListInt),
             separated(1.., quoted_string, ",").map(Value::ListStr),
+            separated(1.., weighted_item, ",").map(Value::ListWeighted),
+            separated(1.., identifier.map(str::to_string), ",").map(Value::ListStr),
         )),
         "]",
     )
@@ -178,11 +353,14 @@ fn list_value(input: &mut &str) -> winnow::error::ModalResult<Value> {
 fn value(input: &mut &str) -> winnow::error::ModalResult<Value> {
     alt((
         parse_distribution.map(Value::Distribution),
+        parse_skew.map(Value::Skew),
         quoted_string.map(Value::Str),
         list_value,
         parse_bool.map(Value::Bool),
         parse_range,
+        parse_float.map(Value::Float),
         parse_int.map(Value::Int),
+        digit1.parse_to().map(Value::UInt),
         parse_bare_str.map(Value::Str),
     ))
     .parse_next(input)
@@ -215,20 +393,91 @@ pub fn parse_options(input: &str) -> HashMap<String, Value> {
     result
 }
 
+/// Parse a pool definition: `name:count:type` for random values, `name:count:msg(pkg.Type)`
+/// for generated messages, or
+/// `name:file:path`, `name:csv:path#column` and `name:jsonl:path[#field]` for values read
+/// from a file, each optionally followed by `:type`.
 pub fn parse_pool_config(input: &str) -> anyhow::Result<crate::PoolConfig> {
-    let mut input = input;
+    let (name, spec) = input
+        .split_once(':')
+        .ok_or_else(|| anyhow::anyhow!("Expected name:count:type or name:source:path"))?;
+    let name = name.to_string();
+
+    let source = match spec.split_once(':') {
+        Some(("file", rest)) => {
+            let (path, value) = split_value_type(rest);
+            PoolSource::File {
+                path: path.into(),
+                value,
+            }
+        }
+        Some(("csv", rest)) => {
+            let (path, value) = split_value_type(rest);
+            let (path, column) = path
+                .rsplit_once('#')
+                .ok_or_else(|| anyhow::anyhow!("CSV pools need a column: {}#column", path))?;
+            PoolSource::Csv {
+                path: path.into(),
+                column: column.to_string(),
+                value,
+            }
+        }
+        Some(("jsonl", rest)) => {
+            let (path, value) = split_value_type(rest);
+            let (path, field) = match path.rsplit_once('#') {
+                Some((path, field)) => (path, Some(field.to_string())),
+                None => (path, None),
+            };
+            PoolSource::Jsonl {
+                path: path.into(),
+                field,
+                value,
+            }
+        }
+        _ => {
+            let mut input = spec;
+            let items = pool_items
+                .parse_next(&mut input)
+                .map_err(|e| anyhow::format_err!("{e}"))?;
+            match pool_message_type.parse_next(&mut input) {
+                Ok(message_type) => PoolSource::Message {
+                    items,
+                    message_type: message_type.to_string(),
+                },
+                Err(_) => PoolSource::Random {
+                    items,
+                    value: parse_value_type
+                        .parse_next(&mut input)
+                        .map_err(|e| anyhow::format_err!("{e}"))?,
+                },
+            }
+        }
+    };
 
-    let (name, _, items, _, value) = (
-        take_until(0.., ":").map(str::to_string),
-        ":",
-        digit1.parse_to::<usize>(),
-        ":",
-        parse_value_type,
+    Ok(PoolConfig { name, source })
+}
+
+fn pool_items(input: &mut &str) -> winnow::error::ModalResult<usize> {
+    terminated(digit1.parse_to(), ':').parse_next(input)
+}
+
+fn pool_message_type<'i>(input: &mut &'i str) -> winnow::error::ModalResult<&'i str> {
+    delimited(
+        Caseless("msg("),
+        take_while(1.., |c: char| c.is_alphanumeric() || c == '_' || c == '.'),
+        ')',
     )
-        .parse_next(&mut input)
-        .map_err(|e| anyhow::format_err!("{e}"))?;
+    .parse_next(input)
+}
 
-    Ok(PoolConfig { name, items, value })
+/// Split a trailing `:type` off a file pool's path
+fn split_value_type(input: &str) -> (&str, Option<ValueType>) {
+    if let Some((path, value)) = input.rsplit_once(':')
+        && let Ok(value) = parse_value_type.parse(value)
+    {
+        return (path, Some(value));
+    }
+    (input, None)
 }
 
 #[cfg(test)]
@@ -266,6 +515,14 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_dotted_keys() {
+        let options = parse_options("sub.amount.max=50 sub.lines[].price.min=1 end.=2 sub.x");
+        assert_eq!(options.len(), 2);
+        assert_eq!(options.get("sub.amount.max"), Some(&Value::Int(50)));
+        assert_eq!(options.get("sub.lines[].price.min"), Some(&Value::Int(1)));
+    }
+
     #[test]
     fn test_parse_complex_options() {
         let input = "command --flag key1=\"quoted string with \\\"escaped quotes\\\"\" key2=[1,2,3,4] key3=simple";
@@ -282,6 +539,15 @@ mod tests {
         assert_eq!(options.get("key3"), Some(&Value::Str("simple".to_string())));
     }
 
+    #[test]
+    fn test_parse_pattern_escapes() {
+        let options = parse_options(r#"pattern="[A-Z]{3}-\d{4}\.\"""#);
+        assert_eq!(
+            options.get("pattern"),
+            Some(&Value::Str(r#"[A-Z]{3}-\d{4}\.""#.to_string()))
+        );
+    }
+
     #[test]
     fn test_parse_empty_and_malformed() {
         // Empty string
@@ -308,7 +574,7 @@ mod tests {
         assert_eq!(options.len(), 5);
         assert_eq!(options.get("range1"), Some(&Value::Range(1, 5)));
         assert_eq!(options.get("range2"), Some(&Value::Range(10, 20)));
-        assert_eq!(options.get("not_range"), None);
+        assert_eq!(options.get("not_range"), Some(&Value::Int(5)));
         assert_eq!(options.get("mixed"), Some(&Value::Range(1, 10)));
         assert_eq!(options.get("text"), Some(&Value::Str("hello".to_string())));
 
@@ -316,5 +582,174 @@ mod tests {
         let input2 = "range3=1 .. 5";
         let options2 = parse_options(input2);
         assert_eq!(options2.get("range3"), Some(&Value::Int(1)));
+
+        let options3 = parse_options("negative=-5..5");
+        assert_eq!(options3.get("negative"), Some(&Value::Range(-5, 5)));
+
+        let options4 = parse_options("sequence=1000.. step=5");
+        assert_eq!(options4.get("sequence"), Some(&Value::RangeFrom(1000)));
+        assert_eq!(options4.get("step"), Some(&Value::Int(5)));
+    }
+
+    #[test]
+    fn test_parse_identifier_lists() {
+        let options = parse_options(
+            "exclude=[PHONE_TYPE_UNSPECIFIED] values=[MOBILE,HOME] weights=[MOBILE:0.7,HOME:3]",
+        );
+
+        assert_eq!(
+            options.get("exclude"),
+            Some(&Value::ListStr(vec!["PHONE_TYPE_UNSPECIFIED".to_string()]))
+        );
+        assert_eq!(
+            options.get("values"),
+            Some(&Value::ListStr(vec![
+                "MOBILE".to_string(),
+                "HOME".to_string()
+            ]))
+        );
+        assert_eq!(
+            options.get("weights"),
+            Some(&Value::ListWeighted(vec![
+                ("MOBILE".to_string(), 0.7),
+                ("HOME".to_string(), 3.0)
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_parse_numbers() {
+        let options = parse_options("min=-3 max=2.5 small=1.5e-3 min_len=4");
+
+        assert_eq!(options.get("min"), Some(&Value::Int(-3)));
+        assert_eq!(options.get("max"), Some(&Value::Float(2.5)));
+        assert_eq!(options.get("small"), Some(&Value::Float(0.0015)));
+        assert_eq!(options.get("min_len"), Some(&Value::Int(4)));
+
+        let options = parse_options("max=18446744073709551615");
+        assert_eq!(options.get("max"), Some(&Value::UInt(u64::MAX)));
+        assert_eq!(Value::integer(-1).as_i128(), Some(-1));
+        assert_eq!(Value::integer(1 << 70), Value::UInt(u64::MAX));
+    }
+
+    #[test]
+    fn test_parse_skew() {
+        let options = parse_options("skew=zipf(1.2) other=hotset(1%,0.8)");
+        assert_eq!(options.get("skew"), Some(&Value::Skew(Skew::Zipf(1.2))));
+        assert_eq!(
+            options.get("other"),
+            Some(&Value::Skew(Skew::Hotset(0.01, 0.8)))
+        );
+    }
+
+    #[test]
+    fn test_parse_distributions() {
+        let options = parse_options(
+            "a=exponential(0.5) b=zipf(10,1.1) c=triangular(0,10,2) d=bernoulli(0.9) \
+             e=categorical([0.5,0.3,0.2]) f=weibull(1,1.5)",
+        );
+        assert_eq!(
+            options.get("a"),
+            Some(&Value::Distribution(Distribution::Exponential(0.5)))
+        );
+        assert_eq!(
+            options.get("c"),
+            Some(&Value::Distribution(Distribution::Triangular(
+                0.0, 10.0, 2.0
+            )))
+        );
+        assert_eq!(
+            options.get("e"),
+            Some(&Value::Distribution(Distribution::Categorical(vec![
+                0.5, 0.3, 0.2
+            ])))
+        );
+        assert_eq!(options.len(), 6);
+        assert_eq!(
+            parse_options("d=empirical(data/sizes.csv)").get("d"),
+            Some(&Value::Distribution(Distribution::Empirical(
+                "data/sizes.csv".to_string()
+            )))
+        );
+        assert_eq!(
+            parse_options("d=empirical(\"my data/sizes (2024).csv\")").get("d"),
+            Some(&Value::Distribution(Distribution::Empirical(
+                "my data/sizes (2024).csv".to_string()
+            )))
+        );
+
+        // Invalid parameters don't make a distribution
+        for text in [
+            "normal(0,-1)",
+            "exponential(0)",
+            "zipf(0,1)",
+            "poisson(-2)",
+            "beta(0,1)",
+            "triangular(5,1,2)",
+            "bernoulli(1.5)",
+            "categorical([0,0])",
+            "gamma(inf,1)",
+        ] {
+            let options = parse_options(&format!("d={}", text));
+            assert!(
+                !matches!(options.get("d"), Some(Value::Distribution(_))),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_pool_config() {
+        let config = parse_pool_config("ids:20:uuid").unwrap();
+        assert_eq!(config.name, "ids");
+        assert_eq!(
+            config.source,
+            PoolSource::Random {
+                items: 20,
+                value: ValueType::Uuid
+            }
+        );
+
+        let config = parse_pool_config("countries:file:data/countries.txt").unwrap();
+        assert_eq!(
+            config.source,
+            PoolSource::File {
+                path: "data/countries.txt".into(),
+                value: None
+            }
+        );
+
+        let config = parse_pool_config("skus:csv:catalog.csv#sku").unwrap();
+        assert_eq!(
+            config.source,
+            PoolSource::Csv {
+                path: "catalog.csv".into(),
+                column: "sku".to_string(),
+                value: None
+            }
+        );
+
+        let config = parse_pool_config("users:jsonl:users.jsonl#user.id:i64").unwrap();
+        assert_eq!(
+            config.source,
+            PoolSource::Jsonl {
+                path: "users.jsonl".into(),
+                field: Some("user.id".to_string()),
+                value: Some(ValueType::I64)
+            }
+        );
+
+        let config = parse_pool_config("devices:50:msg(pkg.Device)").unwrap();
+        assert_eq!(
+            config.source,
+            PoolSource::Message {
+                items: 50,
+                message_type: "pkg.Device".to_string()
+            }
+        );
+
+        assert!(parse_pool_config("skus:csv:catalog.csv").is_err());
+        assert!(parse_pool_config("nothing").is_err());
     }
 }
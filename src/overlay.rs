@@ -0,0 +1,292 @@
+use anyhow::{Context, Result, bail};
+use prost_reflect::{FieldDescriptor, Kind, MessageDescriptor};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+
+use crate::inference::GENERATOR_OPTIONS;
+use crate::option_parser::{self, parse_options};
+use crate::proto_loader::ProtoLoader;
+
+/// An overlay file: generator options by field path, in comment syntax
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OverlayFile {
+    #[serde(default)]
+    fields: BTreeMap<String, String>,
+}
+
+/// Generator options for fields from outside their .proto files, by field full name. They
+/// take precedence over the options in the fields' comments.
+#[derive(Debug, Default)]
+pub struct Overlay {
+    fields: HashMap<String, HashMap<String, option_parser::Value>>,
+}
+
+impl Overlay {
+    /// Load an overlay file, checking its paths against the loaded schemas
+    pub fn load(path: &Path, loader: &ProtoLoader) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Can't read overlay {}", path.display()))?;
+        Overlay::parse(&text, loader).with_context(|| format!("In {}", path.display()))
+    }
+
+    pub(crate) fn parse(text: &str, loader: &ProtoLoader) -> Result<Self> {
+        let file: OverlayFile = toml::from_str(text)?;
+        let mut overlay = Overlay::default();
+        let mut paths: HashMap<String, &str> = HashMap::new();
+
+        for (path, options) in &file.fields {
+            let field = resolve(loader, path)?;
+            if let Some(other) = paths.insert(field.full_name().to_string(), path) {
+                bail!(
+                    "{} and {} are both the field {}",
+                    other,
+                    path,
+                    field.full_name()
+                );
+            }
+            overlay.add(&field, options)?;
+        }
+
+        Ok(overlay)
+    }
+
+    /// Set options for a field, over any it already has here
+    pub fn add(&mut self, field: &FieldDescriptor, options: &str) -> Result<()> {
+        let parsed = parse_options(options);
+        if parsed.is_empty() {
+            bail!("No options in {:?} for {}", options, field.full_name());
+        }
+        merge(
+            self.fields
+                .entry(field.full_name().to_string())
+                .or_default(),
+            &parsed,
+        );
+        Ok(())
+    }
+
+    /// Apply a `--set` override: `PATH=OPTIONS` for a field of `root` (by full path or
+    /// relative to `root`), or `PATH.KEY=VALUE` for a single option
+    pub fn set(
+        &mut self,
+        loader: &ProtoLoader,
+        root: &MessageDescriptor,
+        spec: &str,
+    ) -> Result<()> {
+        let (path, value) = spec
+            .split_once('=')
+            .with_context(|| format!("--set {:?} isn't PATH=OPTIONS", spec))?;
+        let path = path.trim();
+
+        let error = match resolve_in(loader, root, path) {
+            Ok(field) => {
+                let options = value.trim();
+                let options = options
+                    .strip_prefix('"')
+                    .and_then(|s| s.strip_suffix('"'))
+                    .unwrap_or(options);
+                return self.add(&field, options);
+            }
+            Err(error) => error,
+        };
+        // A value with options of its own must be for a field, so don't read a mistyped
+        // field name as an option key
+        match path.rsplit_once('.') {
+            Some((path, key)) if !key.is_empty() && !value.contains('=') => {
+                match resolve_in(loader, root, path) {
+                    Ok(field) => self.add(&field, &format!("{}={}", key, value)),
+                    Err(_) => Err(error),
+                }
+            }
+            _ => Err(error),
+        }
+        .with_context(|| format!("--set {}", spec))
+    }
+
+    /// The options set for a field
+    pub fn options(
+        &self,
+        field: &FieldDescriptor,
+    ) -> Option<&HashMap<String, option_parser::Value>> {
+        self.fields.get(field.full_name())
+    }
+}
+
+/// Put a layer of options over `options`, key by key. A layer choosing a generator (with any
+/// of `GENERATOR_OPTIONS`) replaces all of them from below, so that an overlay `pattern`
+/// isn't shadowed by a `fake` in the comment, which would be tried first.
+pub fn merge(
+    options: &mut HashMap<String, option_parser::Value>,
+    layer: &HashMap<String, option_parser::Value>,
+) {
+    if GENERATOR_OPTIONS.iter().any(|key| layer.contains_key(*key)) {
+        options.retain(|key, _| !GENERATOR_OPTIONS.contains(&key.as_str()));
+    }
+    options.extend(layer.iter().map(|(k, v)| (k.clone(), v.clone())));
+}
+
+/// Find the field named by a path like `person.Person.name`, or `person.Person.phones[].number`
+/// through a nested message. Repeated fields may be marked with `[]` on the way.
+pub fn resolve(loader: &ProtoLoader, path: &str) -> Result<FieldDescriptor> {
+    let segments: Vec<&str> = path.split('.').collect();
+
+    // Message names contain dots too, so try the longest one first
+    let (message, fields) = (1..segments.len())
+        .rev()
+        .find_map(|split| {
+            let message = loader
+                .get_message_descriptor(&segments[..split].join("."))
+                .ok()?;
+            Some((message, &segments[split..]))
+        })
+        .with_context(|| format!("{} doesn't start with a message type", path))?;
+
+    resolve_fields(path, message, fields)
+}
+
+/// Find the field named by a path relative to `root`, like `phones[].number`, or by a full
+/// path to a field nested somewhere in `root`
+pub fn resolve_in(
+    loader: &ProtoLoader,
+    root: &MessageDescriptor,
+    path: &str,
+) -> Result<FieldDescriptor> {
+    if let Ok(field) = resolve(loader, path) {
+        if !reachable(root, field.parent_message()) {
+            bail!(
+                "{} isn't a field of {} or its nested messages",
+                path,
+                root.full_name()
+            );
+        }
+        return Ok(field);
+    }
+
+    let segments: Vec<&str> = path.split('.').collect();
+    resolve_fields(path, root.clone(), &segments)
+}
+
+pub fn resolve_fields(
+    path: &str,
+    mut message: MessageDescriptor,
+    fields: &[&str],
+) -> Result<FieldDescriptor> {
+    let mut field: Option<FieldDescriptor> = None;
+    for (i, segment) in fields.iter().enumerate() {
+        if let Some(field) = &field {
+            message = match field.kind() {
+                Kind::Message(nested) if !field.is_map() => nested,
+                _ => bail!("{}: {} isn't a message field", path, field.name()),
+            };
+        }
+
+        let (name, repeated) = match segment.strip_suffix("[]") {
+            Some(name) => (name, true),
+            None => (*segment, false),
+        };
+        let found = message
+            .get_field_by_name(name)
+            .with_context(|| format!("{}: {} has no field {}", path, message.full_name(), name))?;
+        if repeated && !found.is_list() {
+            bail!("{}: {} isn't a repeated field", path, name);
+        }
+        if repeated && i == fields.len() - 1 {
+            bail!(
+                "{}: [] only goes between a repeated field and its fields",
+                path
+            );
+        }
+        field = Some(found);
+    }
+
+    field.with_context(|| format!("{} doesn't name a field", path))
+}
+
+/// Whether `target` is `root` or the type of a field nested in it
+fn reachable(root: &MessageDescriptor, target: &MessageDescriptor) -> bool {
+    let mut seen = HashSet::new();
+    let mut queue = vec![root.clone()];
+    while let Some(message) = queue.pop() {
+        if message == *target {
+            return true;
+        }
+        if !seen.insert(message.full_name().to_string()) {
+            continue;
+        }
+        queue.extend(message.fields().filter_map(|field| match field.kind() {
+            Kind::Message(nested) => Some(nested),
+            _ => None,
+        }));
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overlay() -> Result<()> {
+        let mut loader = ProtoLoader::new();
+        loader.load_proto_file("proto/person.proto")?;
+
+        let overlay = Overlay::parse(
+            r#"
+            [fields]
+            "person.Person.name" = "fake=name.first_name"
+            "person.Person.phones[].number" = 'pattern="[0-9]{6}"'
+            "#,
+            &loader,
+        )?;
+        let phone = resolve(&loader, "person.Person.phones.number")?;
+        assert_eq!(phone.full_name(), "person.Person.PhoneNumber.number");
+        assert!(overlay.options(&phone).unwrap().contains_key("pattern"));
+
+        for path in [
+            "person.Nobody.name",
+            "person.Person.nickname",
+            "person.Person.name[]",
+            "person.Person.name.first",
+            "person.Person",
+        ] {
+            assert!(resolve(&loader, path).is_err(), "{}", path);
+        }
+        let twice = "[fields]\n\"person.Person.phones.number\" = \"min_len=1\"\n\
+                     \"person.Person.PhoneNumber.number\" = \"max_len=9\"";
+        assert!(Overlay::parse(twice, &loader).is_err());
+        assert!(Overlay::parse("[fields]\n\"person.Person.name\" = \"\"", &loader).is_err());
+
+        let root = loader.get_message_descriptor("person.Person")?;
+        let mut overlay = Overlay::default();
+        overlay.set(&loader, &root, "phones.count=5")?;
+        overlay.set(
+            &loader,
+            &root,
+            "phones[].number=\"pattern=\"[0-9]{4}\" min_len=4\"",
+        )?;
+        overlay.set(&loader, &root, "person.Engineer.age=min=1 max=9")?;
+        let phones = resolve(&loader, "person.Person.phones")?;
+        assert_eq!(
+            overlay.options(&phones).unwrap().get("count"),
+            Some(&option_parser::Value::Int(5))
+        );
+        assert_eq!(overlay.options(&phone).unwrap().len(), 2);
+
+        // A later generator replaces an earlier one, other options add up
+        overlay.set(&loader, &root, "name=fake=name.first_name")?;
+        overlay.set(&loader, &root, "name.words=3")?;
+        overlay.set(&loader, &root, "name.min_len=2")?;
+        let name = resolve(&loader, "person.Person.name")?;
+        let mut keys: Vec<_> = overlay.options(&name).unwrap().keys().collect();
+        keys.sort();
+        assert_eq!(keys, ["min_len", "words"]);
+
+        for spec in ["phones.numbr=pattern=x", "person.Person", "nope.count=1"] {
+            assert!(overlay.set(&loader, &root, spec).is_err(), "{}", spec);
+        }
+
+        Ok(())
+    }
+}
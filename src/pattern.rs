@@ -0,0 +1,173 @@
+use anyhow::{Result, anyhow};
+use rand::Rng;
+use regex_syntax::hir::{Class, Hir, HirKind, Look};
+
+/// Upper bound on extra repetitions for unbounded quantifiers (`*`, `+`, `{n,}`).
+pub const MAX_EXTRA_REPEAT: u32 = 8;
+
+/// A compiled regular expression that can generate matching strings.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    hir: Hir,
+}
+
+impl Pattern {
+    /// Parse a regular expression, rejecting constructs that can't be generated.
+    pub fn new(pattern: &str) -> Result<Self> {
+        let hir = regex_syntax::parse(pattern)
+            .map_err(|e| anyhow!("Invalid pattern '{}': {}", pattern, e))?;
+        check(&hir).map_err(|e| anyhow!("Unsupported pattern '{}': {}", pattern, e))?;
+        Ok(Pattern { hir })
+    }
+
+    /// Generate a random string matching the pattern.
+    pub fn generate<R: Rng + ?Sized>(&self, rng: &mut R) -> String {
+        let mut out = Vec::new();
+        generate(&self.hir, rng, &mut out);
+        // `check` only admits ASCII byte classes, so the output is always valid UTF-8
+        String::from_utf8(out).expect("pattern output is valid UTF-8")
+    }
+}
+
+fn check(hir: &Hir) -> Result<(), String> {
+    match hir.kind() {
+        HirKind::Empty | HirKind::Literal(_) => Ok(()),
+        HirKind::Class(Class::Unicode(class)) if class.ranges().is_empty() => {
+            Err("empty character class".to_string())
+        }
+        HirKind::Class(Class::Unicode(_)) => Ok(()),
+        HirKind::Class(Class::Bytes(class)) => {
+            if class.ranges().is_empty() {
+                Err("empty character class".to_string())
+            } else if class.ranges().iter().any(|r| r.end() > 0x7f) {
+                Err("non-ASCII byte class".to_string())
+            } else {
+                Ok(())
+            }
+        }
+        HirKind::Look(
+            Look::Start | Look::End | Look::StartLF | Look::EndLF | Look::StartCRLF | Look::EndCRLF,
+        ) => Ok(()),
+        HirKind::Look(look) => Err(format!("assertion {:?}", look)),
+        HirKind::Repetition(rep) => check(&rep.sub),
+        HirKind::Capture(cap) => check(&cap.sub),
+        HirKind::Concat(subs) | HirKind::Alternation(subs) => subs.iter().try_for_each(check),
+    }
+}
+
+fn generate<R: Rng + ?Sized>(hir: &Hir, rng: &mut R, out: &mut Vec<u8>) {
+    match hir.kind() {
+        HirKind::Empty | HirKind::Look(_) => (),
+        HirKind::Literal(lit) => out.extend_from_slice(&lit.0),
+        HirKind::Class(Class::Unicode(class)) => {
+            // Weight each range by its size so every code point is equally likely
+            let total: u32 = class
+                .ranges()
+                .iter()
+                .map(|r| r.end() as u32 - r.start() as u32 + 1)
+                .sum();
+            let mut pick = rng.random_range(0..total);
+            for range in class.ranges() {
+                let size = range.end() as u32 - range.start() as u32 + 1;
+                if pick < size {
+                    let c = char::from_u32(range.start() as u32 + pick).unwrap_or(range.start());
+                    let mut buf = [0; 4];
+                    out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                    return;
+                }
+                pick -= size;
+            }
+        }
+        HirKind::Class(Class::Bytes(class)) => {
+            let total: u32 = class
+                .ranges()
+                .iter()
+                .map(|r| r.end() as u32 - r.start() as u32 + 1)
+                .sum();
+            let mut pick = rng.random_range(0..total);
+            for range in class.ranges() {
+                let size = range.end() as u32 - range.start() as u32 + 1;
+                if pick < size {
+                    out.push(range.start() + pick as u8);
+                    return;
+                }
+                pick -= size;
+            }
+        }
+        HirKind::Repetition(rep) => {
+            let max = rep.max.unwrap_or(rep.min + MAX_EXTRA_REPEAT);
+            let count = rng.random_range(rep.min..=max);
+            for _ in 0..count {
+                generate(&rep.sub, rng, out);
+            }
+        }
+        HirKind::Capture(cap) => generate(&cap.sub, rng, out),
+        HirKind::Concat(subs) => {
+            for sub in subs {
+                generate(sub, rng, out);
+            }
+        }
+        HirKind::Alternation(subs) => {
+            let idx = rng.random_range(0..subs.len());
+            generate(&subs[idx], rng, out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_matches(pattern: &str, check: impl Fn(&str) -> bool) {
+        let compiled = Pattern::new(pattern).unwrap();
+        let mut rng = rand::rng();
+        for _ in 0..100 {
+            let s = compiled.generate(&mut rng);
+            assert!(check(&s), "'{}' does not match '{}'", s, pattern);
+        }
+    }
+
+    #[test]
+    fn test_ticket_ids() {
+        assert_matches(r"[A-Z]{3}-\d{4}", |s| {
+            let (prefix, num) = s.split_once('-').unwrap();
+            prefix.len() == 3
+                && prefix.chars().all(|c| c.is_ascii_uppercase())
+                && num.chars().count() == 4
+                && num.chars().all(char::is_numeric)
+        });
+    }
+
+    #[test]
+    fn test_bounded_repetition() {
+        assert_matches("a*b+", |s| {
+            let a = s.chars().take_while(|c| *c == 'a').count();
+            let b = s.len() - a;
+            a <= MAX_EXTRA_REPEAT as usize
+                && (1..=1 + MAX_EXTRA_REPEAT as usize).contains(&b)
+                && s[a..].chars().all(|c| c == 'b')
+        });
+    }
+
+    #[test]
+    fn test_alternation_and_anchors() {
+        assert_matches("^(foo|bar)$", |s| s == "foo" || s == "bar");
+    }
+
+    #[test]
+    fn test_unicode_classes() {
+        assert_matches(r"\p{Lu}{2}[α-ω]", |s| {
+            let chars: Vec<char> = s.chars().collect();
+            chars.len() == 3
+                && chars[..2].iter().all(|c| c.is_uppercase())
+                && ('α'..='ω').contains(&chars[2])
+        });
+    }
+
+    #[test]
+    fn test_unsupported_constructs() {
+        assert!(Pattern::new(r"\bword\b").is_err());
+        assert!(Pattern::new(r"(?-u)[\x80-\xff]").is_err());
+        assert!(Pattern::new("[").is_err());
+    }
+}
@@ -0,0 +1,414 @@
+use anyhow::{Context, Result, anyhow, bail};
+use fake::{Fake, Faker};
+use prost_reflect::{FieldDescriptor, Kind, ReflectMessage, Value};
+use rand::Rng;
+use rand_distr::{Distribution, Zipf};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+use crate::enums;
+use crate::option_parser::{self, Skew, ValueType};
+
+/// Where the values of a pool come from
+#[derive(Clone, Debug, PartialEq)]
+pub enum PoolSource {
+    /// `items` random values of a type
+    Random { items: usize, value: ValueType },
+    /// One value per line of a text file
+    File {
+        path: PathBuf,
+        value: Option<ValueType>,
+    },
+    /// One column of a CSV file with a header row
+    Csv {
+        path: PathBuf,
+        column: String,
+        value: Option<ValueType>,
+    },
+    /// One value per line of a JSON Lines file, or a (dotted) field of each object
+    Jsonl {
+        path: PathBuf,
+        field: Option<String>,
+        value: Option<ValueType>,
+    },
+    /// `items` generated messages of a type, filled in by `ProtoFaker::prepare`
+    Message { items: usize, message_type: String },
+}
+
+/// Build the values of a pool. Values read from files are parsed as `value` when given,
+/// otherwise they are strings (or the JSON type of each value for JSON Lines).
+pub fn load(source: &PoolSource) -> Result<Vec<Value>> {
+    match source {
+        PoolSource::Random { items, value } => Ok(random(*items, value, &mut rand::rng())),
+        PoolSource::File { path, value } => {
+            let text = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            numbered_lines(&text)
+                .map(|(number, line)| {
+                    parse_text(line, value.as_ref())
+                        .with_context(|| format!("{}:{}", path.display(), number))
+                })
+                .collect()
+        }
+        PoolSource::Csv {
+            path,
+            column,
+            value,
+        } => {
+            let mut reader = csv::Reader::from_path(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            let index = reader
+                .headers()?
+                .iter()
+                .position(|header| header == column)
+                .ok_or_else(|| anyhow!("{} has no column {}", path.display(), column))?;
+
+            let mut values = Vec::new();
+            for record in reader.records() {
+                let record = record?;
+                let line = record.position().map_or(0, |p| p.line());
+                match record.get(index) {
+                    Some("") | None => continue,
+                    Some(text) => values.push(
+                        parse_text(text, value.as_ref())
+                            .with_context(|| format!("{}:{}", path.display(), line))?,
+                    ),
+                }
+            }
+            Ok(values)
+        }
+        PoolSource::Jsonl { path, field, value } => {
+            let text = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            numbered_lines(&text)
+                .map(|(number, line)| {
+                    json_line(line, field.as_deref(), value.as_ref())
+                        .with_context(|| format!("{}:{}", path.display(), number))
+                })
+                .collect()
+        }
+        PoolSource::Message { message_type, .. } => {
+            bail!(
+                "Pools of {} messages are generated from the schema",
+                message_type
+            )
+        }
+    }
+}
+
+/// Why a value couldn't be drawn from a pool for a field
+#[derive(Debug, Clone, PartialEq)]
+pub enum PoolError {
+    NotFound {
+        pool: String,
+        field: String,
+    },
+    Empty {
+        pool: String,
+        field: String,
+    },
+    Incompatible {
+        pool: String,
+        field: String,
+        value: String,
+        kind: String,
+    },
+}
+
+impl fmt::Display for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PoolError::NotFound { pool, field } => {
+                write!(f, "Specified Pool '{}' not found on field {}", pool, field)
+            }
+            PoolError::Empty { pool, field } => {
+                write!(f, "Specified Pool '{}' is empty on field {}", pool, field)
+            }
+            PoolError::Incompatible {
+                pool,
+                field,
+                value,
+                kind,
+            } => write!(
+                f,
+                "Specified Pool '{}' holds {} values, which can't be used for {} field {}",
+                pool, value, kind, field
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PoolError {}
+
+/// The field's `skew` option, checked for valid parameters
+pub fn skew(options: &HashMap<String, option_parser::Value>) -> Result<Option<&Skew>> {
+    match options.get("skew") {
+        Some(option_parser::Value::Skew(skew)) => {
+            match skew {
+                Skew::Zipf(s) if s.is_nan() || *s < 0.0 => {
+                    bail!("zipf exponent must not be negative, got {}", s)
+                }
+                Skew::Hotset(keys, picks)
+                    if !(*keys > 0.0 && *keys <= 1.0 && (0.0..=1.0).contains(picks)) =>
+                {
+                    bail!(
+                        "hotset fractions must be between 0 and 1, got {} and {}",
+                        keys,
+                        picks
+                    )
+                }
+                _ => {}
+            }
+            Ok(Some(skew))
+        }
+        Some(skew) => bail!("skew must be zipf(s) or hotset(keys,picks), got {:?}", skew),
+        None => Ok(None),
+    }
+}
+
+/// Pick an index into a pool of `len` values, where lower indexes are more popular.
+/// Since the ranking is the pool's order, the same values stay hot for the whole run.
+pub fn skewed_index<R: Rng + ?Sized>(skew: &Skew, len: usize, rng: &mut R) -> usize {
+    match skew {
+        Skew::Zipf(s) => {
+            let zipf = Zipf::new(len as f64, *s).expect("zipf parameters are checked by skew()");
+            (zipf.sample(rng) as usize).clamp(1, len) - 1
+        }
+        Skew::Hotset(keys, picks) => {
+            let hot = ((len as f64 * keys).ceil() as usize).clamp(1, len);
+            if hot == len || rng.random_bool(*picks) {
+                rng.random_range(0..hot)
+            } else {
+                rng.random_range(hot..len)
+            }
+        }
+    }
+}
+
+/// Convert a pooled value to the type of `field`, widening numbers where no precision is
+/// lost (an `i32` pool can feed `int64` and `double` fields, a `u32` pool `uint64`,
+/// `int64` and `double` fields, ...). Enum fields take integer pools as numbers and string
+/// pools as value names.
+pub fn coerce(value: &Value, field: &FieldDescriptor) -> Option<Value> {
+    match (value, field.kind()) {
+        (Value::I32(v), Kind::Int32 | Kind::Sint32 | Kind::Sfixed32) => Some(Value::I32(*v)),
+        (Value::I32(v), Kind::Int64 | Kind::Sint64 | Kind::Sfixed64) => Some(Value::I64(*v as i64)),
+        (Value::I32(v), Kind::Double) => Some(Value::F64(*v as f64)),
+        (Value::I32(v), Kind::Enum(_)) => Some(Value::EnumNumber(*v)),
+        (Value::I64(v), Kind::Int64 | Kind::Sint64 | Kind::Sfixed64) => Some(Value::I64(*v)),
+        (Value::U32(v), Kind::Uint32 | Kind::Fixed32) => Some(Value::U32(*v)),
+        (Value::U32(v), Kind::Uint64 | Kind::Fixed64) => Some(Value::U64(*v as u64)),
+        (Value::U32(v), Kind::Int64 | Kind::Sint64 | Kind::Sfixed64) => Some(Value::I64(*v as i64)),
+        (Value::U32(v), Kind::Double) => Some(Value::F64(*v as f64)),
+        (Value::U64(v), Kind::Uint64 | Kind::Fixed64) => Some(Value::U64(*v)),
+        (Value::F32(v), Kind::Float) => Some(Value::F32(*v)),
+        (Value::F32(v), Kind::Double) => Some(Value::F64(*v as f64)),
+        (Value::F64(v), Kind::Double) => Some(Value::F64(*v)),
+        (Value::String(s), Kind::String) => Some(Value::String(s.clone())),
+        (Value::String(s), Kind::Enum(enum_type)) => enums::resolve_name(&enum_type, s)
+            .ok()
+            .map(Value::EnumNumber),
+        (Value::Bool(b), Kind::Bool) => Some(Value::Bool(*b)),
+        (Value::Bytes(b), Kind::Bytes) => Some(Value::Bytes(b.clone())),
+        (Value::EnumNumber(n), Kind::Enum(_)) => Some(Value::EnumNumber(*n)),
+        (Value::Message(m), Kind::Message(message_type)) if m.descriptor() == message_type => {
+            Some(Value::Message(m.clone()))
+        }
+        _ => None,
+    }
+}
+
+/// A short description of a pooled value's type for error messages
+pub fn describe(value: &Value) -> String {
+    match value {
+        Value::Bool(_) => "bool".to_string(),
+        Value::I32(_) => "i32".to_string(),
+        Value::I64(_) => "i64".to_string(),
+        Value::U32(_) => "u32".to_string(),
+        Value::U64(_) => "u64".to_string(),
+        Value::F32(_) => "f32".to_string(),
+        Value::F64(_) => "f64".to_string(),
+        Value::String(_) => "string".to_string(),
+        Value::Bytes(_) => "bytes".to_string(),
+        Value::EnumNumber(_) => "enum".to_string(),
+        Value::Message(m) => m.descriptor().full_name().to_string(),
+        Value::List(_) => "list".to_string(),
+        Value::Map(_) => "map".to_string(),
+    }
+}
+
+/// `items` random values of a type
+pub fn random<R: Rng + ?Sized>(items: usize, value: &ValueType, rng: &mut R) -> Vec<Value> {
+    (0..items)
+        .map(|_| match value {
+            ValueType::I32 => Value::I32(Faker.fake_with_rng(rng)),
+            ValueType::I64 => Value::I64(Faker.fake_with_rng(rng)),
+            ValueType::U32 => Value::U32(Faker.fake_with_rng(rng)),
+            ValueType::U64 => Value::U64(Faker.fake_with_rng(rng)),
+            ValueType::F32 => Value::F32(Faker.fake_with_rng(rng)),
+            ValueType::F64 => Value::F64(Faker.fake_with_rng(rng)),
+            ValueType::String => Value::String(Faker.fake_with_rng(rng)),
+            ValueType::Bytes => Value::Bytes(Faker.fake_with_rng::<Vec<u8>, _>(rng).into()),
+            ValueType::Uuid => Value::String(Faker.fake_with_rng::<Uuid, _>(rng).into()),
+        })
+        .collect()
+}
+
+/// Non-blank lines with their 1-based line numbers
+fn numbered_lines(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty())
+}
+
+fn parse_text(text: &str, value: Option<&ValueType>) -> Result<Value> {
+    let parsed = match value {
+        None | Some(ValueType::String) => Value::String(text.to_string()),
+        Some(ValueType::I32) => Value::I32(text.parse()?),
+        Some(ValueType::I64) => Value::I64(text.parse()?),
+        Some(ValueType::U32) => Value::U32(text.parse()?),
+        Some(ValueType::U64) => Value::U64(text.parse()?),
+        Some(ValueType::F32) => Value::F32(text.parse()?),
+        Some(ValueType::F64) => Value::F64(text.parse()?),
+        Some(ValueType::Bytes) => Value::Bytes(text.as_bytes().to_vec().into()),
+        Some(ValueType::Uuid) => Value::String(Uuid::parse_str(text)?.to_string()),
+    };
+    Ok(parsed)
+}
+
+fn json_line(line: &str, field: Option<&str>, value: Option<&ValueType>) -> Result<Value> {
+    let json: serde_json::Value = serde_json::from_str(line)?;
+    let selected = match field {
+        Some(field) => field
+            .split('.')
+            .try_fold(&json, |json, key| json.get(key))
+            .ok_or_else(|| anyhow!("No field {}", field))?,
+        None => &json,
+    };
+    json_value(selected, value)
+}
+
+fn json_value(json: &serde_json::Value, value: Option<&ValueType>) -> Result<Value> {
+    match (json, value) {
+        (serde_json::Value::String(s), _) => parse_text(s, value),
+        (serde_json::Value::Number(_) | serde_json::Value::Bool(_), Some(value)) => {
+            parse_text(&json.to_string(), Some(value))
+        }
+        (serde_json::Value::Bool(b), None) => Ok(Value::Bool(*b)),
+        (serde_json::Value::Number(n), None) => Ok(match (n.as_i64(), n.as_u64()) {
+            (Some(i), _) => Value::I64(i),
+            (None, Some(u)) => Value::U64(u),
+            _ => Value::F64(n.as_f64().unwrap_or_default()),
+        }),
+        _ => bail!("Expected a string, number or bool, got {}", json),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_skewed_index() {
+        let mut rng = rand::rng();
+
+        let mut counts = [0; 100];
+        for _ in 0..10_000 {
+            counts[skewed_index(&Skew::Hotset(0.05, 0.8), 100, &mut rng)] += 1;
+        }
+        let hot: usize = counts[..5].iter().sum();
+        assert!((7_500..8_500).contains(&hot), "{}", hot);
+
+        let mut counts = [0; 100];
+        for _ in 0..10_000 {
+            counts[skewed_index(&Skew::Zipf(1.2), 100, &mut rng)] += 1;
+        }
+        assert!(
+            counts[0] > counts[1] && counts[1] > counts[50],
+            "{:?}",
+            counts
+        );
+
+        assert!(skew(&option_parser::parse_options("skew=zipf(-1.0)")).is_err());
+        assert!(skew(&option_parser::parse_options("skew=hotset(0%,80%)")).is_err());
+        assert!(skew(&option_parser::parse_options("skew=often")).is_err());
+    }
+
+    #[test]
+    fn test_load_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+
+        let path = dir.path().join("countries.txt");
+        fs::write(&path, "Canada\n\nMexico\r\n")?;
+        let values = load(&PoolSource::File { path, value: None })?;
+        assert_eq!(
+            values,
+            vec![
+                Value::String("Canada".to_string()),
+                Value::String("Mexico".to_string())
+            ]
+        );
+
+        let path = dir.path().join("catalog.csv");
+        fs::write(&path, "sku,price\nA-1,10\n\"B,2\",20\n")?;
+        let values = load(&PoolSource::Csv {
+            path: path.clone(),
+            column: "sku".to_string(),
+            value: None,
+        })?;
+        assert_eq!(
+            values,
+            vec![
+                Value::String("A-1".to_string()),
+                Value::String("B,2".to_string())
+            ]
+        );
+        let values = load(&PoolSource::Csv {
+            path: path.clone(),
+            column: "price".to_string(),
+            value: Some(ValueType::I32),
+        })?;
+        assert_eq!(values, vec![Value::I32(10), Value::I32(20)]);
+        assert!(
+            load(&PoolSource::Csv {
+                path,
+                column: "missing".to_string(),
+                value: None,
+            })
+            .is_err()
+        );
+
+        let path = dir.path().join("users.jsonl");
+        fs::write(
+            &path,
+            "{\"user\": {\"id\": 7, \"active\": true}}\n{\"user\": {\"id\": 8}}\n",
+        )?;
+        let values = load(&PoolSource::Jsonl {
+            path: path.clone(),
+            field: Some("user.id".to_string()),
+            value: None,
+        })?;
+        assert_eq!(values, vec![Value::I64(7), Value::I64(8)]);
+        let values = load(&PoolSource::Jsonl {
+            path: path.clone(),
+            field: Some("user.id".to_string()),
+            value: Some(ValueType::U32),
+        })?;
+        assert_eq!(values, vec![Value::U32(7), Value::U32(8)]);
+
+        // The second line has no `active` field
+        let err = load(&PoolSource::Jsonl {
+            path,
+            field: Some("user.active".to_string()),
+            value: None,
+        })
+        .unwrap_err();
+        assert!(format!("{:#}", err).contains("users.jsonl:2"), "{:#}", err);
+
+        Ok(())
+    }
+}
@@ -1,79 +1,605 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use fake::Fake;
-use fake::faker::internet::en::SafeEmail;
-use fake::faker::lorem::en::Sentence;
-use fake::faker::name::en::Name;
-use fake::faker::phone_number::en::PhoneNumber as FakePhoneNumber;
-use prost_reflect::{DynamicMessage, FieldDescriptor, Kind, MessageDescriptor, Value};
+use fake::faker::internet::en::{DomainSuffix, IPv4, IPv6};
+use fake::faker::lorem::en::Word;
+use prost_reflect::prost::Message;
+use prost_reflect::{
+    DynamicMessage, EnumDescriptor, FieldDescriptor, Kind, MessageDescriptor, OneofDescriptor,
+    ReflectMessage, Value,
+};
+use rand::rngs::StdRng;
 use rand::seq::IndexedRandom;
-use rand::{Rng, RngCore};
-use std::collections::HashMap;
+use rand::{Rng, RngCore, SeedableRng};
+use std::borrow::Cow;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
 use std::time::{SystemTime, UNIX_EPOCH};
-use uuid::Uuid;
 
+use crate::binary::{self, BytesMode};
+use crate::enums::EnumChoices;
+use crate::expr::{self, Expr, ExprValue};
+use crate::fakers;
+use crate::inference::{GENERATOR_OPTIONS, Inference};
+use crate::locales::{Locale, LocaleMix, localized};
+use crate::oneofs::OneofChoices;
 use crate::option_parser::parse_options;
+use crate::overlay::{self, Overlay};
+use crate::pattern::Pattern;
+use crate::pools::{self, PoolError, PoolSource};
 use crate::proto_loader::ProtoLoader;
+use crate::sequence::{Scope, Sequence};
+use crate::template::{Source, Template};
+use crate::validate::{FieldRules, Violation};
 use crate::{PoolConfig, distribution, option_parser};
 
 pub struct ProtoFaker {
     pools: HashMap<String, Vec<Value>>,
+    /// Message pools (name, items, message type), generated by `prepare`
+    message_pools: Vec<(String, usize, String)>,
+    patterns: HashMap<String, Pattern>,
+    /// Enum choices, by enum and the options they were built from
+    enum_choices: HashMap<String, EnumChoices>,
+    /// Pools whose values have all been checked against a field, as `pool field`
+    checked_pools: HashSet<String>,
+    /// Context of each message, by `context_key`
+    contexts: HashMap<String, MessageContext>,
+    /// Choices of each oneof, by its full name
+    oneof_choices: HashMap<String, OneofChoices>,
+    /// Samplers of the distributions in options, by distribution
+    samplers: HashMap<String, Arc<distribution::Sampler>>,
+    rules: HashMap<String, Option<FieldRules>>,
+    /// `expr` fields of each message, in evaluation order
+    exprs: HashMap<String, Vec<(FieldDescriptor, Expr)>>,
+    /// Parsed `template` options, by their text
+    templates: HashMap<String, Template>,
+    violation_rate: f64,
+    sparsity: f64,
+    /// How many times each oneof in `mode=cycle` has been generated
+    oneof_turns: Mutex<HashMap<String, usize>>,
+    /// Random pools (name, items, type), rolled again from the seed by `with_seed`
+    random_pools: Vec<(String, usize, option_parser::ValueType)>,
+    seed: Option<u64>,
+    /// Seeds messages generated outside the indexed run (like message pools) in seeded runs
+    unindexed_rng: Mutex<Option<StdRng>>,
+    /// Next position of each run-scoped sequence in unseeded runs
+    sequences: Mutex<HashMap<String, u64>>,
+    /// Values generated so far for each field with `unique=true`, shared by all threads, so
+    /// seeded runs using it depend on generation order
+    unique_values: Mutex<HashMap<String, HashSet<String>>>,
+    locales: LocaleMix,
+    inference: Inference,
+    overlay: Overlay,
+}
+
+/// State for generating one top-level message and everything nested in it
+struct Generation {
+    rng: StdRng,
+    /// Locale of the fakers in this message, unless a field has its own `locale`
+    locale: Locale,
+    /// The message's index, when run-scoped sequences are numbered by it (seeded runs)
+    index: Option<u64>,
+    /// Counters of `scope=parent` sequences, one frame per message being generated
+    frames: Vec<HashMap<String, u64>>,
+    /// Run-scoped sequences used so far in this message
+    used: HashSet<String>,
+}
+
+/// A message being generated and the messages it's nested in, for `expr` paths
+#[derive(Clone, Copy)]
+struct MessageScope<'a> {
+    message: &'a DynamicMessage,
+    parent: Option<&'a MessageScope<'a>>,
+    context: &'a MessageContext,
+}
+
+/// Options a message's fields get from the message and the messages it's nested in
+#[derive(Default, Clone)]
+struct MessageContext {
+    /// Default options from the message's comment, merged with those cascaded from outer
+    /// messages
+    defaults: HashMap<String, option_parser::Value>,
+    /// Defaults passed on to nested messages: the same with `cascade=true`, otherwise only
+    /// those cascaded from outer messages
+    nested: HashMap<String, option_parser::Value>,
+    /// `sub` options of the fields holding this message, by field path and option like
+    /// `amount.max`
+    sub: HashMap<String, option_parser::Value>,
+}
+
+impl MessageScope<'_> {
+    /// Read a field for an `expr`: siblings by name, nested fields with dots and fields of
+    /// the enclosing message with a leading `parent.`
+    fn lookup(&self, path: &[String]) -> Result<ExprValue> {
+        let mut scope = self;
+        let mut path = path;
+        while let [first, rest @ ..] = path
+            && first == "parent"
+            && !rest.is_empty()
+            && scope
+                .message
+                .descriptor()
+                .get_field_by_name("parent")
+                .is_none()
+        {
+            scope = scope.parent.ok_or_else(|| {
+                anyhow::anyhow!(
+                    "{} has no parent message",
+                    scope.message.descriptor().full_name()
+                )
+            })?;
+            path = rest;
+        }
+        lookup_path(scope.message, path)
+    }
 }
 
 impl ProtoFaker {
-    pub fn new(pool_configs: Vec<PoolConfig>) -> Self {
+    pub fn new(pool_configs: Vec<PoolConfig>) -> Result<Self> {
         let mut pools = HashMap::new();
+        let mut message_pools = Vec::new();
+        let mut random_pools = Vec::new();
+
+        for PoolConfig { name, source } in pool_configs {
+            match source {
+                PoolSource::Message {
+                    items,
+                    message_type,
+                } => message_pools.push((name, items, message_type)),
+                PoolSource::Random { items, value } => {
+                    pools.insert(name.clone(), pools::random(items, &value, &mut rand::rng()));
+                    random_pools.push((name, items, value));
+                }
+                source => {
+                    let values =
+                        pools::load(&source).with_context(|| format!("Pool '{}'", name))?;
+                    pools.insert(name, values);
+                }
+            }
+        }
+
+        Ok(ProtoFaker {
+            pools,
+            message_pools,
+            patterns: HashMap::new(),
+            enum_choices: HashMap::new(),
+            oneof_choices: HashMap::new(),
+            contexts: HashMap::new(),
+            checked_pools: HashSet::new(),
+            samplers: HashMap::new(),
+            rules: HashMap::new(),
+            exprs: HashMap::new(),
+            templates: HashMap::new(),
+            violation_rate: 0.0,
+            sparsity: 0.0,
+            oneof_turns: Mutex::new(HashMap::new()),
+            random_pools,
+            seed: None,
+            unindexed_rng: Mutex::new(None),
+            sequences: Mutex::new(HashMap::new()),
+            unique_values: Mutex::new(HashMap::new()),
+            locales: LocaleMix::default(),
+            inference: Inference::default(),
+            overlay: Overlay::default(),
+        })
+    }
+
+    /// Make runs reproducible: random choices are drawn from this seed and each message's
+    /// index, and run-scoped sequences number messages by index instead of by which thread
+    /// gets there first. Random pools are rolled again from the seed.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        for (name, items, value) in &self.random_pools {
+            self.pools
+                .insert(name.clone(), pools::random(*items, value, &mut rng));
+        }
+        self.unindexed_rng = Mutex::new(Some(StdRng::from_rng(&mut rng)));
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Start generating a message, at `index` of the run if it has one
+    fn generation(&self, index: Option<u64>) -> Generation {
+        let mut rng = match (self.seed, index) {
+            (Some(seed), Some(index)) => {
+                StdRng::seed_from_u64(seed ^ index.wrapping_mul(0x9E37_79B9_7F4A_7C15))
+            }
+            _ => match self.unindexed_rng.lock().unwrap().as_mut() {
+                Some(rng) => StdRng::from_rng(rng),
+                None => StdRng::from_rng(&mut rand::rng()),
+            },
+        };
+
+        let locale = self.locales.pick(&mut rng);
+
+        Generation {
+            rng,
+            locale,
+            index: index.filter(|_| self.seed.is_some()),
+            frames: Vec::new(),
+            used: HashSet::new(),
+        }
+    }
+
+    /// Deliberately break `buf.validate` rules on this fraction (0.0 - 1.0) of constrained fields
+    pub fn with_violation_rate(mut self, violation_rate: f64) -> Self {
+        self.violation_rate = violation_rate;
+        self
+    }
+
+    /// Leave this fraction (0.0 - 1.0) of optional fields unset, unless they have a `presence`
+    /// option
+    pub fn with_sparsity(mut self, sparsity: f64) -> Self {
+        self.sparsity = sparsity;
+        self
+    }
+
+    /// Generate fake data in these locales, picking one per top-level message
+    pub fn with_locales(mut self, locales: LocaleMix) -> Self {
+        self.locales = locales;
+        self
+    }
+
+    /// Infer options for unannotated fields with these rules instead of the built-in ones
+    pub fn with_inference(mut self, inference: Inference) -> Self {
+        self.inference = inference;
+        self
+    }
+
+    /// Set field options from outside the .proto files, over the fields' comment options
+    pub fn with_overlay(mut self, overlay: Overlay) -> Self {
+        self.overlay = overlay;
+        self
+    }
+
+    /// A field's options from its message's defaults, its comments, the `sub` options of the
+    /// fields holding its message and the overlay, each over the ones before, plus the options
+    /// of the first inference rule matching it. A rule applies as a whole and only to fields
+    /// that don't choose a generator or set any of the rule's options, in those or
+    /// `buf.validate` rules.
+    fn options_for(
+        &self,
+        loader: &ProtoLoader,
+        field: &FieldDescriptor,
+        context: &MessageContext,
+    ) -> Result<HashMap<String, option_parser::Value>> {
+        let mut options: HashMap<String, option_parser::Value> = context
+            .defaults
+            .iter()
+            .filter(|(key, _)| inherits(key, field))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        overlay::merge(&mut options, &field_options(loader, field)?);
+        let prefix = format!("{}.", field.name());
+        let sub = context
+            .sub
+            .iter()
+            .filter_map(|(key, value)| {
+                let key = key.strip_prefix(&prefix)?;
+                (!key.contains('.')).then(|| (key.to_string(), value.clone()))
+            })
+            .collect();
+        overlay::merge(&mut options, &sub);
+        if let Some(overlay) = self.overlay.options(field) {
+            overlay::merge(&mut options, overlay);
+        }
+        let Some(inferred) = self.inference.options(field) else {
+            return Ok(options);
+        };
+
+        let mut explicit = options.clone();
+        if let Some(rules) = self.field_rules(field) {
+            rules.apply(&mut explicit);
+        }
+        let annotated = GENERATOR_OPTIONS
+            .iter()
+            .copied()
+            .chain(inferred.keys().map(String::as_str))
+            .any(|key| explicit.contains_key(key));
+        if !annotated {
+            options.extend(inferred.iter().map(|(k, v)| (k.clone(), v.clone())));
+        }
+        Ok(options)
+    }
+
+    /// Probability that a field is set, from its `presence` option or the global sparsity.
+    ///
+    /// Required fields are always set, and so are scalars without presence tracking since
+    /// leaving them out is indistinguishable from generating their default value.
+    fn field_presence(
+        &self,
+        field: &FieldDescriptor,
+        options: &HashMap<String, option_parser::Value>,
+        rules: Option<&FieldRules>,
+    ) -> Result<f64> {
+        let required = field.cardinality() == prost_reflect::Cardinality::Required
+            || rules.is_some_and(|r| r.required);
+        let trackable = field.is_list()
+            || field.supports_presence()
+            || matches!(field.kind(), Kind::String | Kind::Bytes);
+        if required || !trackable {
+            return Ok(1.0);
+        }
+
+        match options.get("presence") {
+            Some(option_parser::Value::Float(p)) if (0.0..=1.0).contains(p) => Ok(*p),
+            Some(option_parser::Value::Int(p)) if *p == 0 || *p == 1 => Ok(*p as f64),
+            Some(presence) => Err(anyhow::anyhow!(
+                "Field {}: presence must be a probability between 0 and 1, got {:?}",
+                field.full_name(),
+                presence
+            )),
+            None => Ok(1.0 - self.sparsity),
+        }
+    }
+
+    /// The `buf.validate` rules for a field, read once by `prepare` or on demand otherwise
+    fn field_rules(&self, field: &FieldDescriptor) -> Option<Cow<'_, FieldRules>> {
+        match self.rules.get(field.full_name()) {
+            Some(rules) => rules.as_ref().map(Cow::Borrowed),
+            None => FieldRules::from_field(field).map(Cow::Owned),
+        }
+    }
+
+    /// The `expr` fields of a message in evaluation order, found once by `prepare` or on
+    /// demand otherwise
+    fn message_exprs(
+        &self,
+        loader: &ProtoLoader,
+        message_descriptor: &MessageDescriptor,
+    ) -> Result<Cow<'_, [(FieldDescriptor, Expr)]>> {
+        match self.exprs.get(message_descriptor.full_name()) {
+            Some(exprs) => Ok(Cow::Borrowed(exprs.as_slice())),
+            None => Ok(Cow::Owned(self.expr_fields(loader, message_descriptor)?)),
+        }
+    }
 
-        for PoolConfig { name, items, value } in pool_configs {
-            match value {
-                option_parser::ValueType::I32 => pools.insert(
-                    name,
-                    fake::vec![i32; items].into_iter().map(Value::I32).collect(),
+    /// Validate and compile field options for a message and everything reachable from it.
+    ///
+    /// Errors in options (like unsupported patterns) are reported here, before any
+    /// messages are generated. Message pools are generated here too, in the order they
+    /// were defined, so a message pool can draw from the pools defined before it.
+    pub fn prepare(
+        &mut self,
+        loader: &ProtoLoader,
+        message_descriptor: &MessageDescriptor,
+    ) -> Result<()> {
+        let mut visited = HashSet::new();
+
+        for (name, items, message_type) in std::mem::take(&mut self.message_pools) {
+            let pool_descriptor = loader
+                .get_message_descriptor(&message_type)
+                .with_context(|| format!("Pool '{}'", name))?;
+            self.prepare_message(
+                loader,
+                &pool_descriptor,
+                &mut visited,
+                &HashMap::new(),
+                HashMap::new(),
+            )?;
+
+            let messages = (0..items)
+                .map(|_| {
+                    self.generate_dynamic(loader, &pool_descriptor)
+                        .map(Value::Message)
+                })
+                .collect::<Result<Vec<_>>>()
+                .with_context(|| format!("Pool '{}'", name))?;
+            self.pools.insert(name, messages);
+        }
+
+        self.prepare_message(
+            loader,
+            message_descriptor,
+            &mut visited,
+            &HashMap::new(),
+            HashMap::new(),
+        )
+    }
+
+    /// Prepare a message once for each set of defaults it inherits
+    fn prepare_message(
+        &mut self,
+        loader: &ProtoLoader,
+        message_descriptor: &MessageDescriptor,
+        visited: &mut HashSet<String>,
+        inherited: &HashMap<String, option_parser::Value>,
+        sub: HashMap<String, option_parser::Value>,
+    ) -> Result<()> {
+        let key = context_key(message_descriptor, inherited, &sub);
+        if !visited.insert(key.clone()) {
+            return Ok(());
+        }
+        let context = message_context(loader, message_descriptor, inherited, sub)?;
+        self.contexts.insert(key, context.clone());
+
+        for oneof in message_descriptor.oneofs() {
+            let choices = OneofChoices::new(&oneof, &oneof_options(loader, &oneof)?)
+                .map_err(|e| anyhow::anyhow!("Oneof {}: {}", oneof.full_name(), e))?;
+            self.oneof_choices
+                .insert(oneof.full_name().to_string(), choices);
+        }
+
+        let exprs = self.expr_fields(loader, message_descriptor)?;
+        self.exprs
+            .insert(message_descriptor.full_name().to_string(), exprs);
+
+        for field in message_descriptor.fields() {
+            let mut options = self.options_for(loader, &field, &context)?;
+
+            let rules = FieldRules::from_field(&field);
+            if let Some(rules) = &rules {
+                rules.apply(&mut options);
+            }
+            self.field_presence(&field, &options, rules.as_ref())?;
+            self.rules.insert(field.full_name().to_string(), rules);
+
+            if let Some(option_parser::Value::Str(p)) = options.get("pattern")
+                && !self.patterns.contains_key(p)
+            {
+                let pattern = Pattern::new(p)
+                    .map_err(|e| anyhow::anyhow!("Field {}: {}", field.full_name(), e))?;
+                self.patterns.insert(p.clone(), pattern);
+            }
+
+            Sequence::from_options(&field, &options)
+                .map_err(|e| anyhow::anyhow!("Field {}: {}", field.full_name(), e))?;
+            match options.get("fake") {
+                Some(option_parser::Value::Str(name)) if field.kind() == Kind::String => {
+                    fakers::find(name)
+                        .map_err(|e| anyhow::anyhow!("Field {}: {}", field.full_name(), e))?;
+                }
+                Some(_) if field.kind() != Kind::String => anyhow::bail!(
+                    "Field {}: fake only applies to string fields",
+                    field.full_name()
                 ),
-                option_parser::ValueType::I64 => pools.insert(
-                    name,
-                    fake::vec![i64; items].into_iter().map(Value::I64).collect(),
+                Some(fake) => anyhow::bail!(
+                    "Field {}: fake must be a faker name like address.city_name, got {:?}",
+                    field.full_name(),
+                    fake
                 ),
-                option_parser::ValueType::U32 => pools.insert(
-                    name,
-                    fake::vec![u32; items].into_iter().map(Value::U32).collect(),
+                None => {}
+            }
+            field_locale(&options)
+                .map_err(|e| anyhow::anyhow!("Field {}: {}", field.full_name(), e))?;
+            match distribution_mode(&options)
+                .map_err(|e| anyhow::anyhow!("Field {}: {}", field.full_name(), e))?
+            {
+                DistributionMode::Value if !options.contains_key("distribution") => {
+                    anyhow::bail!(
+                        "Field {}: distribution_mode=value needs a distribution",
+                        field.full_name()
+                    )
+                }
+                DistributionMode::Value if !inherits("distribution", &field) => anyhow::bail!(
+                    "Field {}: distribution_mode=value only applies to numeric and bool fields",
+                    field.full_name()
                 ),
-                option_parser::ValueType::U64 => pools.insert(
-                    name,
-                    fake::vec![u64; items].into_iter().map(Value::U64).collect(),
+                DistributionMode::Value if options.contains_key("pool") => anyhow::bail!(
+                    "Field {}: distributions only bias picks from pools, so distribution_mode \
+                     must be bias",
+                    field.full_name()
                 ),
-                option_parser::ValueType::F32 => pools.insert(
-                    name,
-                    fake::vec![f32; items].into_iter().map(Value::F32).collect(),
+                _ => {}
+            }
+            for key in ["distribution", "count_distribution", "len_distribution"] {
+                match options.get(key) {
+                    // Files of `empirical` distributions are read here, once
+                    Some(option_parser::Value::Distribution(distribution)) => {
+                        if let Entry::Vacant(entry) =
+                            self.samplers.entry(format!("{:?}", distribution))
+                        {
+                            let sampler =
+                                distribution::Sampler::new(distribution).map_err(|e| {
+                                    anyhow::anyhow!("Field {}: {:#}", field.full_name(), e)
+                                })?;
+                            entry.insert(Arc::new(sampler));
+                        }
+                    }
+                    None => {}
+                    Some(distribution) => anyhow::bail!(
+                        "Field {}: {} must be a distribution like normal(0,1) with valid \
+                         parameters, got {:?}",
+                        field.full_name(),
+                        key,
+                        distribution
+                    ),
+                }
+            }
+            if options.contains_key("count_distribution") && !field.is_list() {
+                anyhow::bail!(
+                    "Field {}: count_distribution only applies to repeated fields",
+                    field.full_name()
+                );
+            }
+            if options.contains_key("len_distribution")
+                && !matches!(field.kind(), Kind::String | Kind::Bytes)
+            {
+                anyhow::bail!(
+                    "Field {}: len_distribution only applies to string and bytes fields",
+                    field.full_name()
+                );
+            }
+            if let Some(unit) = options.get("timestamp") {
+                timestamp_integer(&field, unit, &mut rand::rng())
+                    .map_err(|e| anyhow::anyhow!("Field {}: {}", field.full_name(), e))?;
+            }
+            self.prepare_template(loader, &field, &options)
+                .map_err(|e| anyhow::anyhow!("Field {}: {}", field.full_name(), e))?;
+
+            match options.get("count") {
+                Some(option_parser::Value::Int(n)) if *n >= 0 => {}
+                Some(option_parser::Value::Range(s, e)) if 0 <= *s && s <= e => {}
+                Some(count) => anyhow::bail!(
+                    "Field {}: count must be a number or range like 2..5, got {:?}",
+                    field.full_name(),
+                    count
                 ),
-                option_parser::ValueType::F64 => pools.insert(
-                    name,
-                    fake::vec![f64; items].into_iter().map(Value::F64).collect(),
+                None => {}
+            }
+            match options.get("words") {
+                Some(option_parser::Value::Int(n)) if *n >= 0 => {}
+                Some(option_parser::Value::Range(s, e)) if 0 <= *s && s <= e => {}
+                Some(option_parser::Value::ListStr(words)) if !words.is_empty() => {}
+                Some(words) => anyhow::bail!(
+                    "Field {}: words must be a number, range or list of strings, got {:?}",
+                    field.full_name(),
+                    words
                 ),
-                option_parser::ValueType::String => pools.insert(
-                    name,
-                    fake::vec![String; items]
-                        .into_iter()
-                        .map(Value::String)
-                        .collect(),
+                None => {}
+            }
+            match options.get("unique") {
+                Some(option_parser::Value::Bool(_)) | None => {}
+                Some(unique) => anyhow::bail!(
+                    "Field {}: unique must be true or false, got {:?}",
+                    field.full_name(),
+                    unique
                 ),
-                option_parser::ValueType::Bytes => pools.insert(
-                    name,
-                    fake::vec![Vec<u8>; items]
-                        .into_iter()
-                        .map(|b| Value::Bytes(b.into()))
-                        .collect(),
+            }
+            match options.get("unique_within") {
+                Some(option_parser::Value::Str(scope)) if scope == "parent" && field.is_list() => {}
+                Some(scope) => anyhow::bail!(
+                    "Field {}: unique_within=parent only applies to repeated fields, got {:?}",
+                    field.full_name(),
+                    scope
                 ),
-                option_parser::ValueType::Uuid => pools.insert(
-                    name,
-                    fake::vec![Uuid; items]
-                        .into_iter()
-                        .map(|u| Value::String(u.into()))
-                        .collect(),
+                None => {}
+            }
+
+            if let Some(option_parser::Value::Str(name)) = options.get("pool") {
+                pools::skew(&options)
+                    .map_err(|e| anyhow::anyhow!("Field {}: {}", field.full_name(), e))?;
+                self.check_pool(&field, name, |value| pools::coerce(value, &field).is_some())?;
+            }
+            self.prepare_bytes(&field, &options)
+                .map_err(|e| anyhow::anyhow!("Field {}: {}", field.full_name(), e))?;
+            self.prepare_embedded(loader, &field, &options, visited, &context.nested)
+                .map_err(|e| anyhow::anyhow!("Field {}: {}", field.full_name(), e))?;
+
+            match field.kind() {
+                Kind::Enum(enum_type) => {
+                    let key = enum_choices_key(&enum_type, &options);
+                    if let Entry::Vacant(entry) = self.enum_choices.entry(key) {
+                        let choices = EnumChoices::new(&enum_type, &options)
+                            .map_err(|e| anyhow::anyhow!("Field {}: {}", field.full_name(), e))?;
+                        entry.insert(choices);
+                    }
+                }
+                Kind::Message(message_type) => {
+                    check_sub(&field, &options)
+                        .map_err(|e| anyhow::anyhow!("Field {}: {}", field.full_name(), e))?;
+                    let sub = nested_sub(&field, &options, &context);
+                    self.prepare_message(loader, &message_type, visited, &context.nested, sub)?;
+                }
+                _ if options.keys().any(|key| key.starts_with("sub.")) => anyhow::bail!(
+                    "Field {}: sub options only apply to message fields",
+                    field.full_name()
                 ),
-            };
+                _ => (),
+            }
         }
 
-        ProtoFaker { pools }
+        Ok(())
     }
 
     /// Generate a random protobuf message based on its descriptor
@@ -82,37 +608,98 @@ impl ProtoFaker {
         loader: &ProtoLoader,
         message_descriptor: &MessageDescriptor,
     ) -> Result<DynamicMessage> {
-        let mut message = DynamicMessage::new(message_descriptor.clone());
-        let mut rng = rand::rng();
+        let mut generation = self.generation(None);
+        self.generate_message(
+            loader,
+            message_descriptor,
+            &mut generation,
+            None,
+            HashMap::new(),
+        )
+    }
 
-        // Group fields by oneof name
-        let mut oneof_fields: HashMap<String, Vec<FieldDescriptor>> = HashMap::new();
+    /// Generate the message at `index` of the run. With a seed, the same index always
+    /// produces the same message, whichever thread generates it.
+    pub fn generate_indexed(
+        &self,
+        loader: &ProtoLoader,
+        message_descriptor: &MessageDescriptor,
+        index: u64,
+    ) -> Result<DynamicMessage> {
+        let mut generation = self.generation(Some(index));
+        self.generate_message(
+            loader,
+            message_descriptor,
+            &mut generation,
+            None,
+            HashMap::new(),
+        )
+    }
 
-        // Collect all oneofs in the message
-        for oneof in message_descriptor.oneofs() {
-            let oneof_name = oneof.name().to_string();
-            let fields: Vec<FieldDescriptor> = oneof.fields().collect();
-            if !fields.is_empty() {
-                oneof_fields.insert(oneof_name, fields);
-            }
-        }
+    fn generate_message(
+        &self,
+        loader: &ProtoLoader,
+        message_descriptor: &MessageDescriptor,
+        generation: &mut Generation,
+        parent: Option<&MessageScope>,
+        sub: HashMap<String, option_parser::Value>,
+    ) -> Result<DynamicMessage> {
+        let mut message = DynamicMessage::new(message_descriptor.clone());
+        let mut embedded = Vec::new();
+        let no_defaults = HashMap::new();
+        let inherited = parent.map_or(&no_defaults, |parent| &parent.context.nested);
+        let context = match self
+            .contexts
+            .get(&context_key(message_descriptor, inherited, &sub))
+        {
+            Some(context) => Cow::Borrowed(context),
+            None => Cow::Owned(message_context(loader, message_descriptor, inherited, sub)?),
+        };
+        generation.frames.push(HashMap::new());
 
         for field in message_descriptor.fields() {
             // Skip oneof fields - we'll handle them separately
-            if message_descriptor
-                .oneofs()
-                .any(|oneof| oneof.fields().any(|f| f.name() == field.name()))
-            {
+            if field.containing_oneof().is_some() && !is_proto3_optional(&field) {
                 continue;
             }
 
-            let comment = loader.get_comment(
-                message_descriptor.parent_file().name(),
-                message_descriptor.name(),
-                field.name(),
-            )?;
+            let mut options = self.options_for(loader, &field, &context)?;
+            // Derived fields are computed once the rest of the message exists
+            if options.contains_key("expr") {
+                continue;
+            }
+
+            let rules = self.field_rules(&field);
+            let presence = self.field_presence(&field, &options, rules.as_deref())?;
+            if presence < 1.0 && !generation.rng.random_bool(presence) {
+                continue;
+            }
+
+            // Embedded messages wait for the sibling naming their type
+            if options.contains_key("bytes_of") || options.contains_key("bytes_of_field") {
+                embedded.push((field, options));
+                continue;
+            }
+
+            if let Some(rules) = rules.as_deref() {
+                let violation = if self.violation_rate > 0.0
+                    && generation.rng.random_bool(self.violation_rate)
+                {
+                    rules.violate(&field, &options, &mut generation.rng)
+                } else {
+                    None
+                };
 
-            let options = comment.map(|p| parse_options(&p)).unwrap_or(HashMap::new());
+                match violation {
+                    Some(Violation::Unset) => continue,
+                    Some(Violation::Value(value)) => {
+                        message.set_field(&field, value);
+                        continue;
+                    }
+                    Some(Violation::Options(violating)) => options = violating,
+                    None => rules.apply(&mut options),
+                }
+            }
 
             // Check if field is repeated by examining its cardinality
             let is_repeated = field.cardinality() == prost_reflect::Cardinality::Repeated;
@@ -121,146 +708,407 @@ impl ProtoFaker {
                 let count = match options.get("count") {
                     Some(option_parser::Value::Int(i)) => *i..*i,
                     Some(option_parser::Value::Range(s, e)) => *s..*e,
+                    None if options.contains_key("count_distribution") => 0..MAX_SAMPLED_COUNT,
                     None => 1..1,
-                    _ => unimplemented!(),
+                    Some(count) => anyhow::bail!(
+                        "Field {}: count must be a number or range like 2..5, got {:?}",
+                        field.full_name(),
+                        count
+                    ),
                 };
-                let count = rng.random_range(count.start..count.end + 1);
+                let count = match self.sampled_size(
+                    &options,
+                    "count_distribution",
+                    (count.start as f64, count.end as f64),
+                    &mut generation.rng,
+                )? {
+                    Some(count) => count as i64,
+                    None => generation.rng.random_range(count.start..count.end + 1),
+                };
+                let unique_within = options.get("unique_within");
+                let unique = rules.as_deref().is_some_and(|r| r.unique_items)
+                    || matches!(unique_within, Some(option_parser::Value::Str(s)) if s == "parent");
 
                 if count > 0 {
                     let mut values = Vec::new();
                     for _ in 0..count {
-                        values.push(self.generate_field_value(&field, &options, loader)?);
+                        let mut value = self.generate_field_value(
+                            &field,
+                            &options,
+                            loader,
+                            generation,
+                            &MessageScope {
+                                message: &message,
+                                parent,
+                                context: &context,
+                            },
+                        )?;
+                        if unique {
+                            let mut attempts = 0;
+                            while values.contains(&value) && attempts < UNIQUE_ATTEMPTS {
+                                value = self.generate_field_value(
+                                    &field,
+                                    &options,
+                                    loader,
+                                    generation,
+                                    &MessageScope {
+                                        message: &message,
+                                        parent,
+                                        context: &context,
+                                    },
+                                )?;
+                                attempts += 1;
+                            }
+                            if values.contains(&value) {
+                                anyhow::bail!(
+                                    "Field {}: no value missing from the list found in {} \
+                                     attempts after {} unique elements, its values are probably \
+                                     exhausted (lower its count or widen its range)",
+                                    field.full_name(),
+                                    UNIQUE_ATTEMPTS,
+                                    values.len()
+                                );
+                            }
+                        }
+                        values.push(value);
                     }
                     message.set_field(&field, Value::List(values));
                 }
             } else {
-                let value = self.generate_field_value(&field, &options, loader)?;
+                let value = self.generate_field_value(
+                    &field,
+                    &options,
+                    loader,
+                    generation,
+                    &MessageScope {
+                        message: &message,
+                        parent,
+                        context: &context,
+                    },
+                )?;
                 message.set_field(&field, value);
             }
         }
 
         // Handle oneof fields
-        for (_, fields) in oneof_fields {
-            if !fields.is_empty() {
-                // Randomly select one field from the oneof group
-                let idx = rng.random_range(0..fields.len());
-                let selected_field = &fields[idx];
-
-                let comment = loader.get_comment(
-                    message_descriptor.parent_file().name(),
-                    message_descriptor.name(),
-                    selected_field.name(),
-                )?;
+        for oneof in message_descriptor.oneofs() {
+            // proto3 `optional` fields sit in a oneof of their own, but are generated above
+            if oneof.fields().all(|f| is_proto3_optional(&f)) {
+                continue;
+            }
+
+            let choices = match self.oneof_choices.get(oneof.full_name()) {
+                Some(choices) => Cow::Borrowed(choices),
+                None => Cow::Owned(OneofChoices::new(&oneof, &oneof_options(loader, &oneof)?)?),
+            };
+            let turn = || {
+                let mut turns = self.oneof_turns.lock().unwrap();
+                let turn = turns.entry(oneof.full_name().to_string()).or_insert(0);
+                *turn += 1;
+                *turn - 1
+            };
 
-                let options = comment.map(|p| parse_options(&p)).unwrap_or(HashMap::new());
+            if let Some(selected_field) = choices.select(&mut generation.rng, turn) {
+                let mut options = self.options_for(loader, selected_field, &context)?;
+                if let Some(rules) = self.field_rules(selected_field) {
+                    rules.apply(&mut options);
+                }
 
-                let value = self.generate_field_value(selected_field, &options, loader)?;
+                let value = self.generate_field_value(
+                    selected_field,
+                    &options,
+                    loader,
+                    generation,
+                    &MessageScope {
+                        message: &message,
+                        parent,
+                        context: &context,
+                    },
+                )?;
                 message.set_field(selected_field, value);
             }
         }
 
+        for (field, options) in embedded {
+            let scope = MessageScope {
+                message: &message,
+                parent,
+                context: &context,
+            };
+            let Some((type_name, payload)) = self
+                .embedded_message(loader, &options, generation, &scope)
+                .map_err(|e| anyhow::anyhow!("Field {}: {}", field.full_name(), e))?
+            else {
+                continue;
+            };
+            if options.contains_key("bytes_of")
+                && let Some(option_parser::Value::Str(sibling)) = options.get("bytes_of_field")
+            {
+                message.set_field_by_name(sibling, Value::String(type_name));
+            }
+            message.set_field(&field, Value::Bytes(payload.into()));
+        }
+
+        for (field, expr) in self.message_exprs(loader, message_descriptor)?.iter() {
+            let scope = MessageScope {
+                message: &message,
+                parent,
+                context: &context,
+            };
+            let value = expr
+                .eval(&|path| scope.lookup(path), &mut generation.rng)
+                .and_then(|value| expr::to_value(value, field))
+                .map_err(|e| anyhow::anyhow!("Field {}: {}", field.full_name(), e))?;
+            message.set_field(field, value);
+        }
+
+        generation.frames.pop();
         Ok(message)
     }
 
-    /// Generate a random value for a field based on its type and attributes
+    /// Generate and encode the message carried by a `bytes_of` field, with its type name.
+    /// Without `bytes_of` the type is read from the `bytes_of_field` sibling, which must name
+    /// one of `bytes_of_types`, and nothing is generated while that's unset.
+    fn embedded_message(
+        &self,
+        loader: &ProtoLoader,
+        options: &HashMap<String, option_parser::Value>,
+        generation: &mut Generation,
+        scope: &MessageScope,
+    ) -> Result<Option<(String, Vec<u8>)>> {
+        let type_name = match (options.get("bytes_of"), options.get("bytes_of_field")) {
+            (Some(option_parser::Value::Str(name)), _) => name.clone(),
+            (Some(option_parser::Value::ListStr(names)), _) => {
+                names.choose(&mut generation.rng).unwrap().clone()
+            }
+            (None, Some(option_parser::Value::Str(sibling))) => {
+                if !scope.message.has_field_by_name(sibling) {
+                    return Ok(None);
+                }
+                let value = scope.message.get_field_by_name(sibling).unwrap();
+                // A type URL like `type.googleapis.com/pkg.Inner` names `pkg.Inner`
+                let name = match value.as_str().and_then(|name| name.rsplit('/').next()) {
+                    Some("") | None => return Ok(None),
+                    Some(name) => name.to_string(),
+                };
+                let declared = match options.get("bytes_of_types") {
+                    Some(option_parser::Value::Str(declared)) => *declared == name,
+                    Some(option_parser::Value::ListStr(declared)) => declared.contains(&name),
+                    _ => false,
+                };
+                if !declared {
+                    anyhow::bail!("{} names {}, which isn't in bytes_of_types", sibling, name);
+                }
+                name
+            }
+            _ => anyhow::bail!(
+                "bytes_of must be a message type or a list of them, or bytes_of_field a field"
+            ),
+        };
+
+        let descriptor = loader
+            .get_message_descriptor(&type_name)
+            .with_context(|| format!("{} doesn't name a message type", type_name))?;
+        let inner =
+            self.generate_message(loader, &descriptor, generation, Some(scope), HashMap::new())?;
+        Ok(Some((type_name, inner.encode_to_vec())))
+    }
+
+    /// Generate a value for a field, retrying until it's new to the run with `unique=true`
     fn generate_field_value(
         &self,
         field: &FieldDescriptor,
         options: &HashMap<String, option_parser::Value>,
         loader: &ProtoLoader,
+        generation: &mut Generation,
+        scope: &MessageScope,
+    ) -> Result<Value> {
+        if options.get("unique") != Some(&option_parser::Value::Bool(true)) {
+            return self.generate_value(field, options, loader, generation, scope);
+        }
+
+        for _ in 0..UNIQUE_RUN_ATTEMPTS {
+            let value = self.generate_value(field, options, loader, generation, scope)?;
+            let mut unique_values = self.unique_values.lock().unwrap();
+            let seen = unique_values
+                .entry(field.full_name().to_string())
+                .or_default();
+            if seen.insert(format!("{:?}", value)) {
+                return Ok(value);
+            }
+        }
+
+        let used = self
+            .unique_values
+            .lock()
+            .unwrap()
+            .get(field.full_name())
+            .map_or(0, HashSet::len);
+        anyhow::bail!(
+            "Field {}: no unused value found in {} attempts after {} unique values, \
+             its values are probably exhausted (widen its range or drop unique=true)",
+            field.full_name(),
+            UNIQUE_RUN_ATTEMPTS,
+            used
+        )
+    }
+
+    /// Generate a random value for a field based on its type and attributes
+    fn generate_value(
+        &self,
+        field: &FieldDescriptor,
+        options: &HashMap<String, option_parser::Value>,
+        loader: &ProtoLoader,
+        generation: &mut Generation,
+        scope: &MessageScope,
     ) -> Result<Value> {
+        if let Some(option_parser::Value::Str(source)) = options.get("template")
+            && field.kind() == Kind::String
+        {
+            let value = self
+                .render_template(field, options, source, generation, scope)
+                .map_err(|e| anyhow::anyhow!("Field {}: {}", field.full_name(), e))?;
+            let len = self.sampled_len(options, &mut generation.rng)?;
+            return Ok(Value::String(fit_string(
+                value,
+                options,
+                len,
+                &mut generation.rng,
+            )));
+        }
+
+        if let Some(sequence) = Sequence::from_options(field, options)
+            .map_err(|e| anyhow::anyhow!("Field {}: {}", field.full_name(), e))?
+        {
+            let position = self.sequence_position(field, &sequence, generation)?;
+            return sequence
+                .value(position, field)
+                .map_err(|e| anyhow::anyhow!("Field {}: {}", field.full_name(), e));
+        }
+
+        // Nested messages are generated before the rng below borrows the generation
+        if let Kind::Message(message_type) = field.kind()
+            && message_type.full_name() != "google.protobuf.Timestamp"
+            && !options.contains_key("pool")
+        {
+            let sub = nested_sub(field, options, scope.context);
+            let nested_message =
+                self.generate_message(loader, &message_type, generation, Some(scope), sub)?;
+            return Ok(Value::Message(nested_message));
+        }
+
+        // A skew replaces the distribution's bias when picking from a pool
         let distr = options.get("distribution").and_then(|v| match v {
+            option_parser::Value::Distribution(_) if options.contains_key("skew") => None,
             option_parser::Value::Distribution(distribution) => Some(distribution),
             _ => None,
         });
+        // Distributions sampled for values don't bias the rng
+        let (distr, value_distr) = match distribution_mode(options)? {
+            DistributionMode::Value => (None, distr),
+            DistributionMode::Bias => (distr, None),
+        };
 
+        let locale = field_locale(options)?.unwrap_or(generation.locale);
+        let len = self.sampled_len(options, &mut generation.rng)?;
+        let inner = &mut generation.rng;
         let mut rng: Box<dyn RngCore> = match distr {
-            Some(option_parser::Distribution::Uniform) => Box::new(rand::rng()),
-            Some(option_parser::Distribution::Pareto(scale, shape)) => {
-                Box::new(distribution::ParetoRng::new(rand::rng(), *scale, *shape))
-            }
-            Some(option_parser::Distribution::Normal(scale, shape)) => {
-                Box::new(distribution::NormalRng::new(rand::rng(), *scale, *shape))
-            }
-            Some(option_parser::Distribution::LogNormal(scale, shape)) => {
-                Box::new(distribution::LogNormalRng::new(rand::rng(), *scale, *shape))
-            }
-            None => Box::new(rand::rng()),
+            None | Some(option_parser::Distribution::Uniform) => Box::new(inner),
+            Some(distr) => Box::new(distribution::DistributionRng::new(
+                inner,
+                self.sampler(distr)?,
+            )),
         };
 
+        if let Some(option_parser::Value::Str(name)) = options.get("pool") {
+            let skew = pools::skew(options)?;
+            return match self.pool_value(field, name, skew, &mut rng)? {
+                Value::String(s) => Ok(Value::String(fit_string(s, options, len, &mut rng))),
+                value => Ok(value),
+            };
+        }
+
+        if let Some(unit) = options.get("timestamp") {
+            return timestamp_integer(field, unit, &mut rng)
+                .map_err(|e| anyhow::anyhow!("Field {}: {}", field.full_name(), e));
+        }
+
+        if let Some(distr) = value_distr {
+            return Ok(sampled_value(
+                field,
+                options,
+                self.sampler(distr)?.as_ref(),
+                &mut rng,
+            ));
+        }
+
         match field.kind() {
-            Kind::Double => Ok(Value::F64(rng.random_range(-1000.0..1000.0))),
-            Kind::Float => Ok(Value::F32(rng.random_range(-1000.0..1000.0))),
+            Kind::Double => {
+                let (lo, hi) = float_bounds(options, f64::MIN, f64::MAX);
+                Ok(Value::F64(random_float(lo, hi, &mut rng)))
+            }
+            Kind::Float => {
+                let (lo, hi) = float_bounds(options, f32::MIN as f64, f32::MAX as f64);
+                Ok(Value::F32(random_float(lo, hi, &mut rng) as f32))
+            }
+            // Bounds are i128 so every integer type's whole range fits; values are drawn in
+            // the field's own type
             Kind::Int32 | Kind::Sint32 | Kind::Sfixed32 => {
-                Ok(Value::I32(rng.random_range(-1000..1000)))
+                let (lo, hi) = int_bounds(options, (-1000, 999), i32::MIN.into(), i32::MAX.into());
+                Ok(Value::I32(rng.random_range(lo as i64..=hi as i64) as i32))
             }
             Kind::Int64 | Kind::Sint64 | Kind::Sfixed64 => {
-                Ok(Value::I64(rng.random_range(-1000..1000)))
+                let (lo, hi) = int_bounds(options, (-1000, 999), i64::MIN.into(), i64::MAX.into());
+                Ok(Value::I64(rng.random_range(lo as i64..=hi as i64)))
             }
-            Kind::Uint32 | Kind::Fixed32 => Ok(Value::U32(rng.random_range(0..20000))),
-            Kind::Uint64 | Kind::Fixed64 => Ok(Value::U64(rng.random_range(0..20000))),
-            Kind::Bool => Ok(Value::Bool(rng.random_bool(0.5))),
-            Kind::String => {
-                if let Some(option_parser::Value::Str(s)) = options.get("pool") {
-                    if let Some(pool) = self.pools.get(s) {
-                        if let Some(v) = pool.choose(&mut rng) {
-                            if let Value::String(s) = v {
-                                return Ok(Value::String(s.clone()));
-                            }
-                            panic!(
-                                "Specified Pool '{}' has wrong type on field {}",
-                                s,
-                                field.name()
-                            )
-                        }
-                        panic!("Specified Pool '{}' is empty on field {}", s, field.name())
-                    }
-                    panic!("Specified Pool '{}' not found on field {}", s, field.name())
-                }
-
-                match options.get("words") {
-                    Some(&option_parser::Value::Int(i)) => {
-                        return Ok(Value::String(
-                            Sentence(i as usize..i as usize).fake_with_rng(&mut rng),
-                        ));
-                    }
-                    Some(&option_parser::Value::Range(s, e)) => {
-                        return Ok(Value::String(
-                            Sentence(s as usize..e as usize).fake_with_rng(&mut rng),
-                        ));
-                    }
-                    Some(option_parser::Value::ListStr(l)) => {
-                        return Ok(Value::String(l.choose(&mut rng).unwrap().clone()));
-                    }
-                    Some(_) => unimplemented!(),
-                    None => (),
+            Kind::Uint32 | Kind::Fixed32 => {
+                let (lo, hi) = int_bounds(options, (0, 19999), 0, u32::MAX.into());
+                Ok(Value::U32(rng.random_range(lo as i64..=hi as i64) as u32))
+            }
+            Kind::Uint64 | Kind::Fixed64 => {
+                let (lo, hi) = int_bounds(options, (0, 19999), 0, u64::MAX.into());
+                Ok(Value::U64(rng.random_range(lo as u64..=hi as u64)))
+            }
+            Kind::Bool => match distr {
+                // Biased samples near 1 are true, so bernoulli(p) is true with probability p
+                Some(distr) if !matches!(distr, option_parser::Distribution::Uniform) => {
+                    Ok(Value::Bool(rng.random::<f64>() >= 0.5))
                 }
-
-                let field_name = field.name().to_lowercase();
-
-                if Some(option_parser::Value::Str("uuid".to_string()))
-                    == options.get("string").cloned()
-                    || field_name == "uuid"
-                    || field_name == "id"
+                _ => Ok(Value::Bool(rng.random_bool(0.5))),
+            },
+            Kind::String if uses_pattern(options) => {
+                let value = self
+                    .pattern_string(options, locale, len, &mut rng)
+                    .map_err(|e| anyhow::anyhow!("Field {}: {}", field.full_name(), e))?;
+                Ok(Value::String(value))
+            }
+            Kind::String => {
+                let value = self
+                    .generate_string(options, locale, &mut rng)
+                    .map_err(|e| anyhow::anyhow!("Field {}: {}", field.full_name(), e))?;
+                Ok(Value::String(fit_string(value, options, len, &mut rng)))
+            }
+            Kind::Bytes => {
+                let (lo, hi) = len_bounds(options, (4, 19));
+                let bytes = match BytesMode::from_options(options)
+                    .map_err(|e| anyhow::anyhow!("Field {}: {}", field.full_name(), e))?
                 {
-                    return Ok(Value::String(fake::uuid::UUIDv4.fake_with_rng(&mut rng)));
-                }
-
-                match field_name {
-                    s if s.contains("name") => Ok(Value::String(Name().fake_with_rng(&mut rng))),
-                    s if s.contains("email") => {
-                        Ok(Value::String(SafeEmail().fake_with_rng(&mut rng)))
+                    BytesMode::Random => {
+                        let len = len.unwrap_or_else(|| rng.random_range(lo..=hi));
+                        (0..len).map(|_| rng.random::<u8>()).collect()
                     }
-                    s if s.contains("phone") || s.contains("number") => {
-                        Ok(Value::String(FakePhoneNumber().fake_with_rng(&mut rng)))
+                    BytesMode::Utf8 => {
+                        let len = len.unwrap_or_else(|| rng.random_range(lo..=hi));
+                        binary::utf8(locale, len, &mut rng)
                     }
-                    _ => Ok(Value::String(Sentence(1..3).fake_with_rng(&mut rng))),
-                }
-            }
-            Kind::Bytes => {
-                // Generate random bytes
-                let len = rng.random_range(4..20);
-                let bytes: Vec<u8> = (0..len).map(|_| rng.random::<u8>()).collect();
+                    BytesMode::Uuid => binary::uuid(&mut rng),
+                    BytesMode::Sha256 => rng.random::<[u8; 32]>().to_vec(),
+                    BytesMode::Png => binary::png(&mut rng),
+                    BytesMode::Pool(name) => {
+                        let skew = pools::skew(options)?;
+                        self.pool_bytes(field, &name, skew, &mut rng)?
+                    }
+                };
                 Ok(Value::Bytes(bytes.into()))
             }
             Kind::Message(message_type) => {
@@ -279,188 +1127,2517 @@ impl ProtoFaker {
 
                     Ok(Value::Message(timestamp_msg))
                 } else {
-                    // Recursively generate nested message
-                    let nested_message = self.generate_dynamic(loader, &message_type)?;
-                    Ok(Value::Message(nested_message))
+                    unreachable!("nested messages are generated above")
                 }
             }
             Kind::Enum(enum_type) => {
-                // Choose a random enum value
-                let values = enum_type.values();
-                let values: Vec<_> = values.collect();
-                if !values.is_empty() {
-                    let idx = rng.random_range(0..values.len());
-                    Ok(Value::EnumNumber(values[idx].number()))
-                } else {
-                    Ok(Value::EnumNumber(0)) // Default to 0 if no values
-                }
+                // Violations can change the options, so their choices are built here
+                let value = match self
+                    .enum_choices
+                    .get(&enum_choices_key(&enum_type, options))
+                {
+                    Some(choices) => choices.sample(&mut rng),
+                    None => EnumChoices::new(&enum_type, options)?.sample(&mut rng),
+                };
+                Ok(Value::EnumNumber(value))
             }
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::borrow::Cow;
+    /// The sampler of a distribution built by `prepare`. Others (like those of violating
+    /// options) are built here, except `empirical` ones, whose files are only read by `prepare`.
+    fn sampler(
+        &self,
+        distribution: &option_parser::Distribution,
+    ) -> Result<Arc<distribution::Sampler>> {
+        if let Some(sampler) = self.samplers.get(&format!("{:?}", distribution)) {
+            return Ok(sampler.clone());
+        }
+        match distribution {
+            option_parser::Distribution::Empirical(path) => {
+                anyhow::bail!("Empirical distribution {} wasn't loaded by prepare", path)
+            }
+            distribution => Ok(Arc::new(distribution::Sampler::new(distribution)?)),
+        }
+    }
 
-    use super::*;
+    /// A count or length from the distribution in option `key`, within `bounds`
+    fn sampled_size<R: Rng + ?Sized>(
+        &self,
+        options: &HashMap<String, option_parser::Value>,
+        key: &str,
+        (lo, hi): (f64, f64),
+        rng: &mut R,
+    ) -> Result<Option<u64>> {
+        match options.get(key) {
+            Some(option_parser::Value::Distribution(distribution)) => {
+                let sample = self.sampler(distribution)?.sample_within(lo, hi, rng);
+                Ok(Some(sample.round().max(0.0) as u64))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// A length for a string or bytes value from its `len_distribution`, within the
+    /// length options
+    fn sampled_len<R: Rng + ?Sized>(
+        &self,
+        options: &HashMap<String, option_parser::Value>,
+        rng: &mut R,
+    ) -> Result<Option<usize>> {
+        let (lo, hi) = len_bounds(options, (0, MAX_SAMPLED_LEN));
+        let len = self.sampled_size(options, "len_distribution", (lo as f64, hi as f64), rng)?;
+        Ok(len.map(|len| len as usize))
+    }
+
+    /// Claim the next position of a field's sequence.
+    ///
+    /// `scope=parent` counters live in the message holding a repeated field, so each list
+    /// counts from the start, and otherwise in the parent of the field's message, so the
+    /// messages of a repeated field (like line items of an order) share one counter.
+    fn sequence_position(
+        &self,
+        field: &FieldDescriptor,
+        sequence: &Sequence,
+        generation: &mut Generation,
+    ) -> Result<u64> {
+        let key = field.full_name().to_string();
+
+        match (sequence.scope, generation.index) {
+            (Scope::Parent, _) => {
+                let depth = generation.frames.len();
+                let frame = if field.is_list() || depth < 2 {
+                    depth - 1
+                } else {
+                    depth - 2
+                };
+                let counter = generation.frames[frame].entry(key).or_insert(0);
+                *counter += 1;
+                Ok(*counter - 1)
+            }
+            (Scope::Run, Some(index)) => {
+                if !generation.used.insert(key) {
+                    anyhow::bail!(
+                        "Field {}: a seeded run numbers sequences by message, so this field \
+                         can only be generated once per message (try scope=parent)",
+                        field.full_name()
+                    );
+                }
+                Ok(index)
+            }
+            (Scope::Run, None) => {
+                let mut sequences = self.sequences.lock().unwrap();
+                let position = sequences.entry(key).or_insert(0);
+                *position += 1;
+                Ok(*position - 1)
+            }
+        }
+    }
+
+    /// Draw a value from a named pool, converted to the field's type.
+    ///
+    /// With a `skew` the pick follows that popularity model, otherwise it's uniform over
+    /// `rng` (which may be biased by a `distribution`).
+    fn pool_value<R: Rng + ?Sized>(
+        &self,
+        field: &FieldDescriptor,
+        name: &str,
+        skew: Option<&option_parser::Skew>,
+        rng: &mut R,
+    ) -> Result<Value, PoolError> {
+        let value = self.pool_pick(field, name, skew, rng)?;
+        pools::coerce(value, field).ok_or_else(|| PoolError::Incompatible {
+            pool: name.to_string(),
+            field: field.full_name().to_string(),
+            value: pools::describe(value),
+            kind: format!("{:?}", field.kind()),
+        })
+    }
+
+    /// Pick a value from a named pool for a `bytes=pool:NAME` field. Strings are encoded as
+    /// UTF-8.
+    fn pool_bytes<R: Rng + ?Sized>(
+        &self,
+        field: &FieldDescriptor,
+        name: &str,
+        skew: Option<&option_parser::Skew>,
+        rng: &mut R,
+    ) -> Result<Vec<u8>, PoolError> {
+        match self.pool_pick(field, name, skew, rng)? {
+            Value::Bytes(bytes) => Ok(bytes.to_vec()),
+            Value::String(s) => Ok(s.as_bytes().to_vec()),
+            value => Err(PoolError::Incompatible {
+                pool: name.to_string(),
+                field: field.full_name().to_string(),
+                value: pools::describe(value),
+                kind: format!("{:?}", field.kind()),
+            }),
+        }
+    }
+
+    /// Check that a field can take every value of a named pool. Pools read from files
+    /// can mix types, and enum names in a pool may not resolve, so each value is checked.
+    fn check_pool(
+        &mut self,
+        field: &FieldDescriptor,
+        name: &str,
+        accepts: impl Fn(&Value) -> bool,
+    ) -> Result<(), PoolError> {
+        if self
+            .checked_pools
+            .contains(&format!("{} {}", name, field.full_name()))
+        {
+            return Ok(());
+        }
+
+        let pool = self.pools.get(name).ok_or_else(|| PoolError::NotFound {
+            pool: name.to_string(),
+            field: field.full_name().to_string(),
+        })?;
+        if pool.is_empty() {
+            return Err(PoolError::Empty {
+                pool: name.to_string(),
+                field: field.full_name().to_string(),
+            });
+        }
+        if let Some(value) = pool.iter().find(|value| !accepts(value)) {
+            return Err(PoolError::Incompatible {
+                pool: name.to_string(),
+                field: field.full_name().to_string(),
+                value: pools::describe(value),
+                kind: format!("{:?}", field.kind()),
+            });
+        }
+
+        self.checked_pools
+            .insert(format!("{} {}", name, field.full_name()));
+        Ok(())
+    }
+
+    /// Pick a value from a named pool as it's stored, for `field`
+    fn pool_pick<R: Rng + ?Sized>(
+        &self,
+        field: &FieldDescriptor,
+        name: &str,
+        skew: Option<&option_parser::Skew>,
+        rng: &mut R,
+    ) -> Result<&Value, PoolError> {
+        let pool = self.pools.get(name).ok_or_else(|| PoolError::NotFound {
+            pool: name.to_string(),
+            field: field.full_name().to_string(),
+        })?;
+        match skew {
+            Some(skew) if !pool.is_empty() => pool.get(pools::skewed_index(skew, pool.len(), rng)),
+            _ => pool.choose(rng),
+        }
+        .ok_or_else(|| PoolError::Empty {
+            pool: name.to_string(),
+            field: field.full_name().to_string(),
+        })
+    }
+
+    /// Fill in a field's `template` option. The `{seq}` placeholders of one value share a
+    /// position in the field's sequence.
+    fn render_template(
+        &self,
+        field: &FieldDescriptor,
+        options: &HashMap<String, option_parser::Value>,
+        source: &str,
+        generation: &mut Generation,
+        scope: &MessageScope,
+    ) -> Result<String> {
+        let parsed;
+        let template = match self.templates.get(source) {
+            Some(template) => template,
+            None => {
+                parsed = Template::parse(source)?;
+                &parsed
+            }
+        };
+
+        let number = match template.sources().any(|s| matches!(s, Source::Seq(_))) {
+            true => {
+                let sequence = Sequence::from_options(field, options)?.unwrap_or_default();
+                let position = self.sequence_position(field, &sequence, generation)?;
+                Some(sequence.number(position)?)
+            }
+            false => None,
+        };
+        let skew = pools::skew(options)?;
+        let locale = field_locale(options)?.unwrap_or(generation.locale);
+
+        template.render(locale, &mut generation.rng, |source, rng| match source {
+            Source::Seq(_) => Ok(ExprValue::Int(
+                number.expect("sequence position is claimed"),
+            )),
+            Source::Pool(name) => expr::from_value(self.pool_pick(field, name, skew, rng)?),
+            Source::Field(path) => scope.lookup(path),
+            source => unreachable!("templates generate {:?} themselves", source),
+        })
+    }
+
+    /// Check a field's `template` option and cache it. Fields read by a template must be
+    /// generated before it: siblings declared earlier that aren't `expr` or oneof fields.
+    fn prepare_template(
+        &mut self,
+        loader: &ProtoLoader,
+        field: &FieldDescriptor,
+        options: &HashMap<String, option_parser::Value>,
+    ) -> Result<()> {
+        let source = match options.get("template") {
+            Some(option_parser::Value::Str(source)) => source,
+            Some(template) => anyhow::bail!("template must be a quoted string, got {:?}", template),
+            None => return Ok(()),
+        };
+        if field.kind() != Kind::String {
+            anyhow::bail!("template only applies to string fields");
+        }
+        let template = Template::parse(source)?;
+
+        if options.contains_key("format") {
+            anyhow::bail!("format doesn't apply to templates, use a placeholder like {{seq:06}}");
+        }
+        if options.contains_key("sequence")
+            && !template.sources().any(|s| matches!(s, Source::Seq(_)))
+        {
+            anyhow::bail!("sequence needs a {{seq}} placeholder in the template");
+        }
+
+        let message = field.parent_message();
+        for source in template.sources() {
+            match source {
+                Source::Pool(name) => {
+                    let pool = self.pools.get(name).ok_or_else(|| PoolError::NotFound {
+                        pool: name.to_string(),
+                        field: field.full_name().to_string(),
+                    })?;
+                    if pool.is_empty() {
+                        return Err(PoolError::Empty {
+                            pool: name.to_string(),
+                            field: field.full_name().to_string(),
+                        }
+                        .into());
+                    }
+                    // Every value is checked, as any of them can be picked
+                    for value in pool.iter() {
+                        expr::from_value(value).map_err(|e| {
+                            anyhow::anyhow!("Pool {} can't be used in a template: {}", name, e)
+                        })?;
+                    }
+                }
+                Source::Field(path) => {
+                    let sibling = match message.get_field_by_name(&path[0]) {
+                        Some(sibling) => sibling,
+                        None if path[0] == "parent" => continue,
+                        None => anyhow::bail!("{} has no field {}", message.full_name(), path[0]),
+                    };
+                    let declared_before = message.fields().position(|f| f == sibling)
+                        < message.fields().position(|f| f == *field);
+                    let in_oneof =
+                        sibling.containing_oneof().is_some() && !is_proto3_optional(&sibling);
+                    if !declared_before
+                        || in_oneof
+                        || self
+                            .options_for(loader, &sibling, &MessageContext::default())?
+                            .contains_key("expr")
+                    {
+                        anyhow::bail!(
+                            "template reads {}, which isn't generated before it (only siblings \
+                             declared earlier that aren't expr or oneof fields are)",
+                            sibling.name()
+                        );
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        self.templates.insert(source.clone(), template);
+        Ok(())
+    }
+
+    /// Check a field's `bytes` and `len` options
+    fn prepare_bytes(
+        &mut self,
+        field: &FieldDescriptor,
+        options: &HashMap<String, option_parser::Value>,
+    ) -> Result<()> {
+        match options.get("len") {
+            Some(option_parser::Value::Int(len)) if *len >= 0 => {}
+            Some(option_parser::Value::Range(lo, hi)) if 0 <= *lo && lo <= hi => {}
+            Some(len) => anyhow::bail!("len must be a length or range like 4..16, got {:?}", len),
+            None => {}
+        }
+        if field.kind() != Kind::Bytes {
+            if options.contains_key("bytes") {
+                anyhow::bail!("bytes only applies to bytes fields");
+            }
+            return Ok(());
+        }
+
+        let mode = BytesMode::from_options(options)?;
+        if !mode.sized() && options.contains_key("len") {
+            anyhow::bail!(
+                "len only applies to random and utf8 bytes, the other modes have a fixed size"
+            );
+        }
+        if let BytesMode::Pool(name) = &mode {
+            pools::skew(options)?;
+            self.check_pool(field, name, |value| {
+                matches!(value, Value::Bytes(_) | Value::String(_))
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Check a field's `bytes_of` and `bytes_of_field` options and prepare the message types
+    /// it may carry
+    fn prepare_embedded(
+        &mut self,
+        loader: &ProtoLoader,
+        field: &FieldDescriptor,
+        options: &HashMap<String, option_parser::Value>,
+        visited: &mut HashSet<String>,
+        inherited: &HashMap<String, option_parser::Value>,
+    ) -> Result<()> {
+        let (types, sibling) = (options.get("bytes_of"), options.get("bytes_of_field"));
+        let read_types = options.get("bytes_of_types");
+        if types.is_none() && sibling.is_none() {
+            if read_types.is_some() {
+                anyhow::bail!("bytes_of_types only applies with bytes_of_field");
+            }
+            return Ok(());
+        }
+        if field.kind() != Kind::Bytes
+            || field.is_list()
+            || (field.containing_oneof().is_some() && !is_proto3_optional(field))
+        {
+            anyhow::bail!("bytes_of only applies to singular bytes fields outside oneofs");
+        }
+        if options.contains_key("bytes") || options.contains_key("len") {
+            anyhow::bail!("bytes_of can't be combined with bytes or len");
+        }
+
+        // Alone, `bytes_of_field` reads the type from the sibling, and the types it may name
+        // are declared by `bytes_of_types` so they can be prepared
+        let (key, types) = match (types, read_types) {
+            (Some(_), Some(_)) => anyhow::bail!(
+                "bytes_of_types lists the types bytes_of_field may name, bytes_of picks them itself"
+            ),
+            (Some(types), None) => ("bytes_of", types),
+            (None, Some(types)) => ("bytes_of_types", types),
+            (None, None) => anyhow::bail!(
+                "bytes_of_field alone needs bytes_of_types=[...] listing the types it may name"
+            ),
+        };
+        let types = match types {
+            option_parser::Value::Str(name) => vec![name.clone()],
+            option_parser::Value::ListStr(names) => names.clone(),
+            types => anyhow::bail!(
+                "{} must be a message type or a list of them, got {:?}",
+                key,
+                types
+            ),
+        };
+        for name in &types {
+            let descriptor = loader.get_message_descriptor(name)?;
+            self.prepare_message(loader, &descriptor, visited, inherited, HashMap::new())?;
+        }
+
+        let sibling = match sibling {
+            Some(option_parser::Value::Str(name)) => name,
+            Some(sibling) => {
+                anyhow::bail!("bytes_of_field must be a field name, got {:?}", sibling)
+            }
+            None => return Ok(()),
+        };
+        let message = field.parent_message();
+        match message.get_field_by_name(sibling) {
+            Some(sibling) if sibling.kind() == Kind::String && !sibling.is_list() => {
+                if read_types.is_some()
+                    && self
+                        .options_for(loader, &sibling, &MessageContext::default())?
+                        .contains_key("expr")
+                {
+                    anyhow::bail!(
+                        "bytes_of_field reads {}, which is an expr field and computed after it",
+                        sibling.name()
+                    );
+                }
+                Ok(())
+            }
+            _ => anyhow::bail!(
+                "bytes_of_field must name a singular string field of {}, got {}",
+                message.full_name(),
+                sibling
+            ),
+        }
+    }
+
+    /// Generate the raw contents of a string field, before length and affix options apply
+    /// Generate a string from a `pattern` within the length options. Padding or truncating
+    /// could break the pattern, so values are drawn again until one fits.
+    fn pattern_string<R: Rng + ?Sized>(
+        &self,
+        options: &HashMap<String, option_parser::Value>,
+        locale: Locale,
+        len: Option<usize>,
+        rng: &mut R,
+    ) -> Result<String> {
+        for _ in 0..PATTERN_ATTEMPTS {
+            let value = self.generate_string(options, locale, rng)?;
+            let body_len = value.chars().count();
+            let fitted = fit_string(value, options, len, rng);
+            let affix_len = ["prefix", "suffix"]
+                .iter()
+                .filter_map(|key| match options.get(*key) {
+                    Some(option_parser::Value::Str(s)) => Some(s.chars().count()),
+                    _ => None,
+                })
+                .sum::<usize>();
+            if fitted.chars().count() == affix_len + body_len {
+                return Ok(fitted);
+            }
+        }
+        let (min_len, max_len) =
+            len.map_or_else(|| len_bounds(options, (0, usize::MAX)), |len| (len, len));
+        anyhow::bail!(
+            "pattern produced no value of {} to {} characters in {} attempts",
+            min_len,
+            max_len,
+            PATTERN_ATTEMPTS
+        )
+    }
+
+    fn generate_string<R: Rng + ?Sized>(
+        &self,
+        options: &HashMap<String, option_parser::Value>,
+        locale: Locale,
+        rng: &mut R,
+    ) -> Result<String> {
+        match options.get("words") {
+            Some(&option_parser::Value::Int(i)) => {
+                return Ok(localized!(
+                    locale,
+                    lorem::Sentence(i as usize..i as usize),
+                    rng
+                ));
+            }
+            Some(&option_parser::Value::Range(s, e)) => {
+                return Ok(localized!(
+                    locale,
+                    lorem::Sentence(s as usize..e as usize),
+                    rng
+                ));
+            }
+            Some(option_parser::Value::ListStr(l)) => {
+                return Ok(l.choose(rng).unwrap().clone());
+            }
+            Some(words) => anyhow::bail!(
+                "words must be a number, range or list of strings, got {:?}",
+                words
+            ),
+            None => (),
+        }
+
+        if let Some(option_parser::Value::Str(name)) = options.get("fake") {
+            return Ok(fakers::find(name)?.generate(locale, rng));
+        }
+
+        if let Some(option_parser::Value::Str(p)) = options.get("pattern") {
+            let value = match self.patterns.get(p) {
+                Some(pattern) => pattern.generate(rng),
+                None => Pattern::new(p)?.generate(rng),
+            };
+            return Ok(value);
+        }
+
+        match options.get("string") {
+            Some(option_parser::Value::Str(s)) if s == "uuid" => {
+                return Ok(fake::uuid::UUIDv4.fake_with_rng(rng));
+            }
+            Some(option_parser::Value::Str(s)) if s == "email" => {
+                return Ok(localized!(locale, internet::SafeEmail(), rng));
+            }
+            Some(option_parser::Value::Str(s)) if s == "hostname" || s == "address" => {
+                return Ok(fake_hostname(rng));
+            }
+            Some(option_parser::Value::Str(s)) if s == "ipv4" || s == "ip" => {
+                return Ok(IPv4().fake_with_rng(rng));
+            }
+            Some(option_parser::Value::Str(s)) if s == "ipv6" => {
+                return Ok(IPv6().fake_with_rng(rng));
+            }
+            Some(option_parser::Value::Str(s)) if s == "uri" || s == "uri_ref" => {
+                let path: String = Word().fake_with_rng(rng);
+                return Ok(format!("https://{}/{}", fake_hostname(rng), path));
+            }
+            _ => (),
+        }
+
+        Ok(localized!(locale, lorem::Sentence(1..3), rng))
+    }
+
+    /// The `expr` fields of a message, ordered so fields are evaluated after the sibling
+    /// `expr` fields they read
+    fn expr_fields(
+        &self,
+        loader: &ProtoLoader,
+        message_descriptor: &MessageDescriptor,
+    ) -> Result<Vec<(FieldDescriptor, Expr)>> {
+        let mut exprs = Vec::new();
+        for field in message_descriptor.fields() {
+            let options = self.options_for(loader, &field, &MessageContext::default())?;
+            let source = match options.get("expr") {
+                Some(option_parser::Value::Str(source)) => source,
+                Some(expr) => anyhow::bail!(
+                    "Field {}: expr must be a quoted expression, got {:?}",
+                    field.full_name(),
+                    expr
+                ),
+                None => continue,
+            };
+            if field.is_list() || field.is_map() {
+                anyhow::bail!(
+                    "Field {}: expr only applies to singular fields",
+                    field.full_name()
+                );
+            }
+            if field.containing_oneof().is_some() && !is_proto3_optional(&field) {
+                anyhow::bail!("Field {}: expr can't be used in a oneof", field.full_name());
+            }
+
+            let expr = Expr::parse(source)
+                .map_err(|e| anyhow::anyhow!("Field {}: {}", field.full_name(), e))?;
+            for path in expr.paths() {
+                let first = path[0].as_str();
+                if first != "parent" && message_descriptor.get_field_by_name(first).is_none() {
+                    anyhow::bail!(
+                        "Field {}: {} has no field {}",
+                        field.full_name(),
+                        message_descriptor.full_name(),
+                        first
+                    );
+                }
+            }
+            exprs.push((field, expr));
+        }
+
+        // Depth-first topological sort over references to sibling `expr` fields
+        fn visit(
+            i: usize,
+            exprs: &[(FieldDescriptor, Expr)],
+            state: &mut [u8],
+            stack: &mut Vec<usize>,
+            order: &mut Vec<usize>,
+        ) -> Result<()> {
+            match state[i] {
+                2 => return Ok(()),
+                1 => {
+                    let start = stack.iter().position(|&j| j == i).unwrap_or(0);
+                    let cycle: Vec<&str> = stack[start..]
+                        .iter()
+                        .chain([&i])
+                        .map(|&j| exprs[j].0.name())
+                        .collect();
+                    anyhow::bail!("expr fields depend on each other: {}", cycle.join(" -> "));
+                }
+                _ => {}
+            }
+
+            state[i] = 1;
+            stack.push(i);
+            for path in exprs[i].1.paths() {
+                if let Some(j) = exprs.iter().position(|(f, _)| f.name() == path[0]) {
+                    visit(j, exprs, state, stack, order)?;
+                }
+            }
+            stack.pop();
+            state[i] = 2;
+            order.push(i);
+            Ok(())
+        }
+
+        let mut state = vec![0; exprs.len()];
+        let mut order = Vec::new();
+        for i in 0..exprs.len() {
+            visit(i, &exprs, &mut state, &mut Vec::new(), &mut order).map_err(|e| {
+                anyhow::anyhow!("Message {}: {}", message_descriptor.full_name(), e)
+            })?;
+        }
+
+        let mut exprs: Vec<Option<(FieldDescriptor, Expr)>> = exprs.into_iter().map(Some).collect();
+        Ok(order.into_iter().filter_map(|i| exprs[i].take()).collect())
+    }
+}
+
+/// Parse the generator options from the comments attached to a field
+fn field_options(
+    loader: &ProtoLoader,
+    field: &FieldDescriptor,
+) -> Result<HashMap<String, option_parser::Value>> {
+    let comment = loader.get_comment(field.parent_file().name(), field.path())?;
+
+    Ok(comment.map(|p| parse_options(&p)).unwrap_or_default())
+}
+
+/// The options a message's fields get from outside their own comments: the message's
+/// default options, merged with those `inherited` from outer messages, and `sub` options
+fn message_context(
+    loader: &ProtoLoader,
+    message_descriptor: &MessageDescriptor,
+    inherited: &HashMap<String, option_parser::Value>,
+    sub: HashMap<String, option_parser::Value>,
+) -> Result<MessageContext> {
+    let comment = loader.get_comment(
+        message_descriptor.parent_file().name(),
+        message_descriptor.path(),
+    )?;
+    let mut own = comment.map(|c| parse_options(&c)).unwrap_or_default();
+
+    let cascade = match own.remove("cascade") {
+        Some(option_parser::Value::Bool(cascade)) => cascade,
+        Some(cascade) => anyhow::bail!(
+            "Message {}: cascade must be true or false, got {:?}",
+            message_descriptor.full_name(),
+            cascade
+        ),
+        None => false,
+    };
+    for key in own.keys() {
+        if SINGLE_FIELD_OPTIONS.contains(&key.as_str()) || key.starts_with("sub.") {
+            anyhow::bail!(
+                "Message {}: {} only applies to a single field, not as a message default",
+                message_descriptor.full_name(),
+                key
+            );
+        }
+    }
+
+    let mut defaults = inherited.clone();
+    defaults.extend(own);
+    let nested = match cascade {
+        true => defaults.clone(),
+        false => inherited.clone(),
+    };
+    Ok(MessageContext {
+        defaults,
+        nested,
+        sub,
+    })
+}
+
+/// Key of a message's context in `ProtoFaker::contexts`: the message with the defaults and
+/// `sub` options it gets
+fn context_key(
+    message_descriptor: &MessageDescriptor,
+    inherited: &HashMap<String, option_parser::Value>,
+    sub: &HashMap<String, option_parser::Value>,
+) -> String {
+    let mut key: Vec<String> = inherited
+        .iter()
+        .map(|(key, value)| format!("{}={:?}", key, value))
+        .chain(
+            sub.iter()
+                .map(|(key, value)| format!("sub.{}={:?}", key, value)),
+        )
+        .collect();
+    key.sort();
+    format!("{} {}", message_descriptor.full_name(), key.join(" "))
+}
+
+/// The `sub` options for the fields of the message a field holds: its own `sub.PATH.KEY`
+/// options, then those for paths through it from outer messages, which take precedence
+fn nested_sub(
+    field: &FieldDescriptor,
+    options: &HashMap<String, option_parser::Value>,
+    context: &MessageContext,
+) -> HashMap<String, option_parser::Value> {
+    let mut sub: HashMap<String, option_parser::Value> = options
+        .iter()
+        .filter_map(|(key, value)| {
+            Some((key.strip_prefix("sub.")?.replace("[]", ""), value.clone()))
+        })
+        .collect();
+
+    let prefix = format!("{}.", field.name());
+    sub.extend(context.sub.iter().filter_map(|(key, value)| {
+        let rest = key
+            .strip_prefix(&prefix)
+            .filter(|rest| rest.contains('.'))?;
+        Some((rest.to_string(), value.clone()))
+    }));
+    sub
+}
+
+/// Check that every `sub.PATH.KEY` option of a message field names a field of its message
+fn check_sub(
+    field: &FieldDescriptor,
+    options: &HashMap<String, option_parser::Value>,
+) -> Result<()> {
+    let Kind::Message(message_type) = field.kind() else {
+        return Ok(());
+    };
+
+    for key in options.keys() {
+        let Some(path) = key.strip_prefix("sub.") else {
+            continue;
+        };
+        let Some((path, option)) = path.rsplit_once('.') else {
+            anyhow::bail!(
+                "{} needs a field path and an option, like sub.amount.max",
+                key
+            );
+        };
+        // Derived and embedded fields are worked out once per message type, not per place
+        if ["expr", "bytes_of", "bytes_of_field", "bytes_of_types"].contains(&option) {
+            anyhow::bail!("{} can't be set with sub, only on the field itself", option);
+        }
+        let segments: Vec<&str> = path.split('.').collect();
+        overlay::resolve_fields(key, message_type.clone(), &segments)?;
+    }
+    Ok(())
+}
+
+/// Options that only make sense on one field, and aren't accepted as message defaults
+const SINGLE_FIELD_OPTIONS: &[&str] = &[
+    "expr",
+    "bytes_of",
+    "bytes_of_field",
+    "bytes_of_types",
+    "pool",
+    "skew",
+    "unique",
+];
+
+/// Whether a message default applies to a field. Options for another kind of field are
+/// left out, so `words` doesn't reach integers and `min` doesn't reach strings.
+fn inherits(key: &str, field: &FieldDescriptor) -> bool {
+    let kind = field.kind();
+    let integer = matches!(
+        kind,
+        Kind::Int32
+            | Kind::Sint32
+            | Kind::Sfixed32
+            | Kind::Int64
+            | Kind::Sint64
+            | Kind::Sfixed64
+            | Kind::Uint32
+            | Kind::Fixed32
+            | Kind::Uint64
+            | Kind::Fixed64
+    );
+    let numeric = integer || matches!(kind, Kind::Float | Kind::Double);
+
+    match key {
+        "words" | "fake" | "pattern" | "template" | "string" | "prefix" | "suffix" => {
+            kind == Kind::String
+        }
+        "bytes" => kind == Kind::Bytes,
+        "len" | "min_len" | "max_len" | "len_distribution" => {
+            matches!(kind, Kind::String | Kind::Bytes)
+        }
+        "timestamp" => integer,
+        "min" | "max" => numeric,
+        "distribution" | "distribution_mode" => numeric || kind == Kind::Bool,
+        "sequence" | "step" | "format" | "scope" => integer || kind == Kind::String,
+        "values" | "exclude" | "weights" | "unknown" => matches!(kind, Kind::Enum(_)),
+        "count" | "unique_within" | "count_distribution" => field.is_list(),
+        "locale" => matches!(kind, Kind::String | Kind::Bytes),
+        "presence" => true,
+        // Options of a single field are rejected by `message_context`, and unknown ones
+        // don't reach any field
+        _ => false,
+    }
+}
+
+/// How a field's `distribution` is used
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DistributionMode {
+    /// Bias where in the field's range values fall
+    Bias,
+    /// Use samples as values
+    Value,
+}
+
+fn distribution_mode(options: &HashMap<String, option_parser::Value>) -> Result<DistributionMode> {
+    match options.get("distribution_mode") {
+        Some(option_parser::Value::Str(mode)) if mode == "bias" => Ok(DistributionMode::Bias),
+        Some(option_parser::Value::Str(mode)) if mode == "value" => Ok(DistributionMode::Value),
+        Some(mode) => anyhow::bail!("distribution_mode must be bias or value, got {:?}", mode),
+        // Observed data is in the field's own units, but only biases picks from a pool
+        None => {
+            match options.get("distribution") {
+                Some(option_parser::Value::Distribution(
+                    option_parser::Distribution::Empirical(_),
+                )) if !options.contains_key("pool") => Ok(DistributionMode::Value),
+                _ => Ok(DistributionMode::Bias),
+            }
+        }
+    }
+}
+
+/// A value sampled from a distribution in its own units (`distribution_mode=value`),
+/// truncated to `min`/`max`, rounded for integer fields and clamped to the field's type
+fn sampled_value<R: Rng + ?Sized>(
+    field: &FieldDescriptor,
+    options: &HashMap<String, option_parser::Value>,
+    sampler: &distribution::Sampler,
+    rng: &mut R,
+) -> Value {
+    let (type_min, type_max, integer) = match field.kind() {
+        Kind::Double => (f64::MIN, f64::MAX, false),
+        Kind::Float => (f32::MIN as f64, f32::MAX as f64, false),
+        Kind::Int32 | Kind::Sint32 | Kind::Sfixed32 => (i32::MIN as f64, i32::MAX as f64, true),
+        Kind::Int64 | Kind::Sint64 | Kind::Sfixed64 => (i64::MIN as f64, i64::MAX as f64, true),
+        Kind::Uint32 | Kind::Fixed32 => (0.0, u32::MAX as f64, true),
+        Kind::Uint64 | Kind::Fixed64 => (0.0, u64::MAX as f64, true),
+        _ => (0.0, 1.0, true),
+    };
+    let bound = |key| match options.get(key) {
+        Some(option_parser::Value::Float(f)) => Some(*f),
+        Some(value) => value.as_i128().map(|i| i as f64),
+        None => None,
+    };
+    let mut lo = bound("min").unwrap_or(type_min).max(type_min);
+    let mut hi = bound("max").unwrap_or(type_max).min(type_max).max(lo);
+    if integer {
+        (lo, hi) = (lo.ceil(), hi.floor().max(lo.ceil()));
+    }
+
+    let mut sample = sampler.sample_within(lo, hi, rng);
+    if integer {
+        sample = sample.round().clamp(lo, hi);
+    }
+    // Float to integer casts saturate, which keeps the type's extremes in range
+    match field.kind() {
+        Kind::Double => Value::F64(sample),
+        Kind::Float => Value::F32(sample as f32),
+        Kind::Int32 | Kind::Sint32 | Kind::Sfixed32 => Value::I32(sample as i32),
+        Kind::Int64 | Kind::Sint64 | Kind::Sfixed64 => Value::I64(sample as i64),
+        Kind::Uint32 | Kind::Fixed32 => Value::U32(sample as u32),
+        Kind::Uint64 | Kind::Fixed64 => Value::U64(sample as u64),
+        _ => Value::Bool(sample >= 0.5),
+    }
+}
+
+/// A field's own `locale` option
+fn field_locale(options: &HashMap<String, option_parser::Value>) -> Result<Option<Locale>> {
+    match options.get("locale") {
+        Some(option_parser::Value::Str(locale)) => Ok(Some(Locale::parse(locale)?)),
+        Some(locale) => anyhow::bail!("locale must be a locale like de_DE, got {:?}", locale),
+        None => Ok(None),
+    }
+}
+
+/// The current time give or take a day, as an integer field counting `unit`s (`seconds`,
+/// `millis`, `micros` or `nanos`) since the Unix epoch
+fn timestamp_integer<R: Rng + ?Sized>(
+    field: &FieldDescriptor,
+    unit: &option_parser::Value,
+    rng: &mut R,
+) -> Result<Value> {
+    let per_second: i64 = match unit {
+        option_parser::Value::Str(unit) if unit == "seconds" => 1,
+        option_parser::Value::Str(unit) if unit == "millis" => 1_000,
+        option_parser::Value::Str(unit) if unit == "micros" => 1_000_000,
+        option_parser::Value::Str(unit) if unit == "nanos" => 1_000_000_000,
+        unit => anyhow::bail!(
+            "timestamp must be seconds, millis, micros or nanos, got {:?}",
+            unit
+        ),
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let now = (now.as_nanos() / (1_000_000_000 / per_second) as u128) as i64;
+    let value = now + rng.random_range(-86400 * per_second..86400 * per_second);
+
+    let overflow = || anyhow::anyhow!("{:?} timestamps don't fit a {:?} field", unit, field.kind());
+    match field.kind() {
+        Kind::Int32 | Kind::Sint32 | Kind::Sfixed32 => {
+            i32::try_from(value).map(Value::I32).map_err(|_| overflow())
+        }
+        Kind::Uint32 | Kind::Fixed32 => {
+            u32::try_from(value).map(Value::U32).map_err(|_| overflow())
+        }
+        Kind::Int64 | Kind::Sint64 | Kind::Sfixed64 => Ok(Value::I64(value)),
+        Kind::Uint64 | Kind::Fixed64 => {
+            u64::try_from(value).map(Value::U64).map_err(|_| overflow())
+        }
+        _ => anyhow::bail!("timestamp only applies to integer fields"),
+    }
+}
+
+/// Whether a field is a proto3 `optional` field, which is wrapped in a synthetic oneof
+fn is_proto3_optional(field: &FieldDescriptor) -> bool {
+    field.field_descriptor_proto().proto3_optional()
+}
+
+/// Cache key of the choices of an enum field: the enum and the options `EnumChoices` reads
+fn enum_choices_key(
+    enum_type: &EnumDescriptor,
+    options: &HashMap<String, option_parser::Value>,
+) -> String {
+    let option = |key| options.get(key).map(|value| format!("{:?}", value));
+    format!(
+        "{} {:?} {:?} {:?} {:?}",
+        enum_type.full_name(),
+        option("values"),
+        option("exclude"),
+        option("weights"),
+        option("unknown")
+    )
+}
+
+/// Parse the generator options from the comments attached to a oneof declaration
+fn oneof_options(
+    loader: &ProtoLoader,
+    oneof: &OneofDescriptor,
+) -> Result<HashMap<String, option_parser::Value>> {
+    let comment = loader.get_comment(oneof.parent_file().name(), oneof.path())?;
+
+    Ok(comment.map(|p| parse_options(&p)).unwrap_or_default())
+}
+
+/// Read a (dotted) field path from a message for an `expr`
+fn lookup_path(message: &DynamicMessage, path: &[String]) -> Result<ExprValue> {
+    let (name, rest) = path
+        .split_first()
+        .ok_or_else(|| anyhow::anyhow!("Empty field path"))?;
+    let field = message
+        .descriptor()
+        .get_field_by_name(name)
+        .ok_or_else(|| {
+            anyhow::anyhow!("{} has no field {}", message.descriptor().full_name(), name)
+        })?;
+    let value = message.get_field(&field);
+
+    match (value.as_ref(), rest) {
+        (value, []) => expr::from_value(value),
+        (Value::Message(nested), rest) => lookup_path(nested, rest),
+        (_, rest) => anyhow::bail!("{} has no field {}", field.full_name(), rest.join(".")),
+    }
+}
+
+/// Number of attempts at generating a `pattern` value within the length options
+const PATTERN_ATTEMPTS: usize = 100;
+
+/// Largest number of items `count_distribution` draws without a `count`, so a heavy-tailed
+/// sample can't exhaust memory
+const MAX_SAMPLED_COUNT: i64 = 1000;
+
+/// Largest length `len_distribution` draws without a `len` or `max_len`
+const MAX_SAMPLED_LEN: usize = 65536;
+
+/// Number of attempts at generating a value not already present in a unique list
+const UNIQUE_ATTEMPTS: usize = 100;
+
+/// Number of attempts at generating a value not used before in the run for `unique=true`
+const UNIQUE_RUN_ATTEMPTS: usize = 100;
+
+fn fake_hostname<R: Rng + ?Sized>(rng: &mut R) -> String {
+    let word: String = Word().fake_with_rng(rng);
+    let suffix: String = DomainSuffix().fake_with_rng(rng);
+    format!("{}.{}", word, suffix)
+}
+
+/// Inclusive integer bounds from the `min`/`max` options.
+///
+/// Missing bounds fall back to `default`, shifted along when only one side is given so the
+/// range keeps the default width.
+fn int_bounds(
+    options: &HashMap<String, option_parser::Value>,
+    default: (i128, i128),
+    type_min: i128,
+    type_max: i128,
+) -> (i128, i128) {
+    let min = match options.get("min") {
+        Some(option_parser::Value::Float(f)) => Some(f.ceil() as i128),
+        Some(min) => min.as_i128(),
+        None => None,
+    };
+    let max = match options.get("max") {
+        Some(option_parser::Value::Float(f)) => Some(f.floor() as i128),
+        Some(max) => max.as_i128(),
+        None => None,
+    };
+
+    let width = default.1 - default.0;
+    let (lo, hi) = match (min, max) {
+        (Some(lo), Some(hi)) => (lo, hi),
+        (Some(lo), None) if lo <= default.1 => (lo, default.1),
+        (Some(lo), None) => (lo, lo.saturating_add(width)),
+        (None, Some(hi)) if hi >= default.0 => (default.0, hi),
+        (None, Some(hi)) => (hi.saturating_sub(width), hi),
+        (None, None) => default,
+    };
+
+    let lo = lo.clamp(type_min, type_max);
+    (lo, hi.clamp(lo, type_max))
+}
+
+/// Inclusive float bounds from the `min`/`max` options, defaulting to -1000..1000
+fn float_bounds(
+    options: &HashMap<String, option_parser::Value>,
+    type_min: f64,
+    type_max: f64,
+) -> (f64, f64) {
+    let get = |key| match options.get(key) {
+        Some(option_parser::Value::Float(f)) => Some(*f),
+        Some(value) => value.as_i128().map(|i| i as f64),
+        None => None,
+    };
+
+    let width = 2000.0;
+    let (lo, hi) = match (get("min"), get("max")) {
+        (Some(lo), Some(hi)) => (lo, hi),
+        (Some(lo), None) if lo <= 1000.0 => (lo, 1000.0),
+        (Some(lo), None) => (lo, lo + width),
+        (None, Some(hi)) if hi >= -1000.0 => (-1000.0, hi),
+        (None, Some(hi)) => (hi - width, hi),
+        (None, None) => (-1000.0, 1000.0),
+    };
+
+    let lo = lo.clamp(type_min, type_max);
+    (lo, hi.clamp(lo, type_max))
+}
+
+/// A float in `[lo, hi]`. Spans too wide for an f64, like `f64::MIN..=f64::MAX`, are
+/// interpolated between the ends instead of measured.
+fn random_float<R: Rng + ?Sized>(lo: f64, hi: f64, rng: &mut R) -> f64 {
+    if (hi - lo).is_finite() {
+        return rng.random_range(lo..=hi);
+    }
+    let r: f64 = rng.random();
+    (lo * (1.0 - r) + hi * r).clamp(lo, hi)
+}
+
+/// Inclusive length bounds from the `min_len`/`max_len` options
+fn len_bounds(
+    options: &HashMap<String, option_parser::Value>,
+    default: (usize, usize),
+) -> (usize, usize) {
+    let get = |key| match options.get(key) {
+        Some(option_parser::Value::Int(i)) => Some((*i).max(0) as usize),
+        _ => None,
+    };
+    // `len` replaces the default, and `min_len`/`max_len` narrow it
+    let default = match options.get("len") {
+        Some(&option_parser::Value::Int(len)) => (len.max(0) as usize, len.max(0) as usize),
+        Some(&option_parser::Value::Range(lo, hi)) => {
+            (lo.max(0) as usize, hi.max(lo).max(0) as usize)
+        }
+        _ => default,
+    };
+
+    match (get("min_len"), get("max_len")) {
+        (Some(lo), Some(hi)) => (lo, hi.max(lo)),
+        (Some(lo), None) => (lo, default.1.max(lo)),
+        (None, Some(hi)) => (default.0.min(hi), hi),
+        (None, None) => default,
+    }
+}
+
+/// Whether a string field's value comes from its `pattern`, which `words` and `fake` are
+/// tried before
+fn uses_pattern(options: &HashMap<String, option_parser::Value>) -> bool {
+    options.contains_key("pattern")
+        && !options.contains_key("words")
+        && !options.contains_key("fake")
+}
+
+/// Apply the `prefix`/`suffix` and `min_len`/`max_len` options to a generated string, or
+/// make it exactly `len` long if given.
+///
+/// Lengths are counted in characters and include the prefix and suffix. Strings are
+/// truncated or padded with lowercase letters to fit.
+fn fit_string<R: Rng + ?Sized>(
+    value: String,
+    options: &HashMap<String, option_parser::Value>,
+    len: Option<usize>,
+    rng: &mut R,
+) -> String {
+    let affix = |key| match options.get(key) {
+        Some(option_parser::Value::Str(s)) => s.as_str(),
+        _ => "",
+    };
+    let (prefix, suffix) = (affix("prefix"), affix("suffix"));
+
+    let mut body = value;
+    if len.is_some()
+        || ["len", "min_len", "max_len"]
+            .iter()
+            .any(|key| options.contains_key(*key))
+    {
+        let (min_len, max_len) =
+            len.map_or_else(|| len_bounds(options, (0, usize::MAX)), |len| (len, len));
+        let fixed = prefix.chars().count() + suffix.chars().count();
+        let body_min = min_len.saturating_sub(fixed);
+        let body_max = max_len.saturating_sub(fixed);
+
+        let len = body.chars().count();
+        if len > body_max {
+            body = body.chars().take(body_max).collect();
+        } else if len < body_min {
+            body.extend((len..body_min).map(|_| rng.random_range('a'..='z')));
+        }
+    }
+
+    format!("{}{}{}", prefix, body, suffix)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::*;
     use crate::proto_loader::ProtoLoader;
     use prost::Message;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_generate_dynamic_message() -> Result<()> {
+        let mut loader = ProtoLoader::new();
+        loader.load_proto_file("proto/person.proto")?;
+
+        let message_descriptor = loader.get_message_descriptor("person.Person")?;
+        let faker = ProtoFaker::new(vec![PoolConfig {
+            name: String::from("user_id"),
+            source: PoolSource::Random {
+                items: 20,
+                value: option_parser::ValueType::Uuid,
+            },
+        }])?;
+
+        let message = faker.generate_dynamic(&loader, &message_descriptor)?;
+
+        // Verify the message has data
+        assert!(message.get_field_by_name("name").is_some());
+
+        // Test serialization
+        let encoded = message.encode_to_vec();
+        assert!(!encoded.is_empty());
+
+        // Test deserialization
+        let decoded = DynamicMessage::decode(message_descriptor, encoded.as_slice())?;
+
+        // Verify some fields match
+        if let Some(name) = message.get_field_by_name("name")
+            && let Some(decoded_name) = decoded.get_field_by_name("name")
+        {
+            assert_eq!(name, decoded_name);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_multiple_message_types() -> Result<()> {
+        let mut loader = ProtoLoader::new();
+        loader.load_proto_file("proto/person.proto")?;
+
+        let faker = ProtoFaker::new(vec![PoolConfig {
+            name: String::from("user_id"),
+            source: PoolSource::Random {
+                items: 20,
+                value: option_parser::ValueType::Uuid,
+            },
+        }])?;
+
+        // Test Person message
+        let person_descriptor = loader.get_message_descriptor("person.Person")?;
+        let person = faker.generate_dynamic(&loader, &person_descriptor)?;
+        assert!(person.get_field_by_name("name").is_some());
+
+        // Test PhoneNumber message
+        let phone_descriptor = loader.get_message_descriptor("person.Person.PhoneNumber")?;
+        let phone = faker.generate_dynamic(&loader, &phone_descriptor)?;
+        assert!(phone.get_field_by_name("number").is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_comment_attributes() -> Result<()> {
+        let mut loader = ProtoLoader::new();
+        loader.load_proto_file("proto/person.proto")?;
+
+        let message_descriptor = loader.get_message_descriptor("person.Person")?;
+        let faker = ProtoFaker::new(vec![PoolConfig {
+            name: String::from("user_id"),
+            source: PoolSource::Random {
+                items: 20,
+                value: option_parser::ValueType::Uuid,
+            },
+        }])?;
+
+        let message = faker.generate_dynamic(&loader, &message_descriptor)?;
+
+        // Check that name has 1-3 words
+        if let Some(Cow::Borrowed(Value::String(name))) = message.get_field_by_name("name") {
+            let word_count = name.split_whitespace().count();
+            assert!(
+                (1..=3).contains(&word_count),
+                "Name '{}' should have 1-3 words, has {}",
+                name,
+                word_count
+            );
+        } else {
+            panic!("No Name Field");
+        }
+
+        // Check that uuid is a valid UUID
+        if let Some(Cow::Borrowed(Value::String(uuid_str))) = message.get_field_by_name("uuid") {
+            assert!(
+                Uuid::parse_str(uuid_str).is_ok(),
+                "Invalid UUID: {}",
+                uuid_str
+            );
+        } else {
+            panic!("No UUID");
+        }
+
+        // Check that phones has 1-3 entries
+        if let Some(Cow::Borrowed(Value::List(phones))) = message.get_field_by_name("phones") {
+            assert!(
+                !phones.is_empty() && phones.len() <= 3,
+                "Phones should have 1-3 entries, has {}",
+                phones.len()
+            );
+        } else {
+            panic!("No phones");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_oneof_fields() -> Result<()> {
+        let mut loader = ProtoLoader::new();
+        loader.load_proto_file("proto/person.proto")?;
+
+        let message_descriptor = loader.get_message_descriptor("person.Person")?;
+        let faker = ProtoFaker::new(vec![PoolConfig {
+            name: String::from("user_id"),
+            source: PoolSource::Random {
+                items: 20,
+                value: option_parser::ValueType::Uuid,
+            },
+        }])?;
+
+        // Generate multiple messages to ensure oneof fields are properly handled
+        for _ in 0..10 {
+            let message = faker.generate_dynamic(&loader, &message_descriptor)?;
+
+            // Check that exactly one of the oneof fields is set
+            let engineer = message.get_field_by_name("engineer");
+            let manager = message.get_field_by_name("manager");
+
+            let engineer = engineer.unwrap();
+            let manager = manager.unwrap();
+            let e_age = engineer
+                .as_message()
+                .unwrap()
+                .get_field_by_name("age")
+                .unwrap()
+                .as_i32()
+                .unwrap();
+            let m_age = manager
+                .as_message()
+                .unwrap()
+                .get_field_by_name("age")
+                .unwrap()
+                .as_i32()
+                .unwrap();
+
+            // Either engineer or manager should be set, but not both
+            assert!(
+                (e_age != 0) ^ (m_age != 0), // XOR operator ensures exactly one is true
+                "Oneof field should have exactly one value set"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pattern_option() -> Result<()> {
+        let mut loader = ProtoLoader::new();
+        loader.load_proto_file("proto/person.proto")?;
+
+        let message_descriptor = loader.get_message_descriptor("person.Person")?;
+        let mut faker = ProtoFaker::new(vec![PoolConfig {
+            name: String::from("user_id"),
+            source: PoolSource::Random {
+                items: 20,
+                value: option_parser::ValueType::Uuid,
+            },
+        }])?;
+        faker.prepare(&loader, &message_descriptor)?;
+
+        for _ in 0..10 {
+            let message = faker.generate_dynamic(&loader, &message_descriptor)?;
+            let ticket = message.get_field_by_name("ticket").unwrap();
+            let ticket = ticket.as_str().unwrap();
+            let (prefix, number) = ticket.split_once('-').unwrap();
+            assert!(prefix.len() == 3 && prefix.chars().all(|c| c.is_ascii_uppercase()));
+            assert!(number.len() == 4 && number.chars().all(|c| c.is_ascii_digit()));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prepare_rejects_unsupported_pattern() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("bad.proto");
+        std::fs::write(
+            &path,
+            "syntax = \"proto3\";\npackage bad;\nmessage Bad {\n  string s = 1; // pattern=\"\\bx\"\n}\n",
+        )?;
+
+        let mut loader = ProtoLoader::new();
+        loader.load_proto_file(&path)?;
+        let message_descriptor = loader.get_message_descriptor("bad.Bad")?;
+
+        let mut faker = ProtoFaker::new(vec![])?;
+        let err = faker.prepare(&loader, &message_descriptor).unwrap_err();
+        assert!(err.to_string().contains("bad.Bad.s"), "{}", err);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_buf_validate_rules() -> Result<()> {
+        let mut loader = ProtoLoader::new();
+        loader.load_proto_file("proto/validated.proto")?;
+
+        let message_descriptor = loader.get_message_descriptor("validated.Account")?;
+        let mut faker = ProtoFaker::new(vec![])?;
+        faker.prepare(&loader, &message_descriptor)?;
+
+        for _ in 0..50 {
+            let message = faker.generate_dynamic(&loader, &message_descriptor)?;
+            let get = |name| message.get_field_by_name(name).unwrap().into_owned();
+
+            let handle = get("handle");
+            let handle_len = handle.as_str().unwrap().chars().count();
+            assert!((5..=8).contains(&handle_len), "{:?}", handle);
+
+            assert!(get("email").as_str().unwrap().contains('@'));
+            assert!(Uuid::parse_str(get("id").as_str().unwrap()).is_ok());
+
+            let code = get("code");
+            let code = code.as_str().unwrap();
+            assert_eq!(code.len(), 6, "{}", code);
+            assert!(code[..2].chars().all(|c| c.is_ascii_lowercase()) && &code[2..3] == "-");
+
+            assert!((18..=119).contains(&get("age").as_i32().unwrap()));
+            assert!((0.0..=10.0).contains(&get("balance").as_f64().unwrap()));
+            assert!(get("visits").as_u64().unwrap() > 50000);
+
+            let tags = get("tags");
+            let tags = tags.as_list().unwrap();
+            assert!((2..=4).contains(&tags.len()), "{:?}", tags);
+            for (i, tag) in tags.iter().enumerate() {
+                assert!(tag.as_str().unwrap().starts_with("tag-"));
+                assert!(!tags[i + 1..].contains(tag));
+            }
+
+            let nick = get("nick");
+            let nick = nick.as_str().unwrap();
+            assert_eq!(nick.len(), 3, "{}", nick);
+            assert!(nick.starts_with(|c: char| c.is_ascii_uppercase()));
+            assert!(get("contact").as_str().unwrap().contains('@'));
+            let ticket = get("ticket");
+            let ticket = ticket.as_str().unwrap();
+            assert!((3..=5).contains(&ticket.len()), "{}", ticket);
+            assert!(ticket.starts_with('x') && ticket[1..].chars().all(|c| c.is_ascii_digit()));
+
+            assert!((0..=2).contains(&get("status").as_enum_number().unwrap()));
+            assert!([1, 2].contains(&get("previous").as_enum_number().unwrap()));
+            assert!(message.has_field_by_name("profile"));
+            let profile = get("profile");
+            let bio = profile
+                .as_message()
+                .unwrap()
+                .get_field_by_name("bio")
+                .unwrap();
+            assert!(bio.as_str().unwrap().chars().count() <= 20);
+        }
+
+        // Bounds at the ends of a type's range
+        let extremes = loader.get_message_descriptor("validated.Extremes")?;
+        faker.prepare(&loader, &extremes)?;
+        for _ in 0..50 {
+            let message = faker.generate_dynamic(&loader, &extremes)?;
+            let get = |name| message.get_field_by_name(name).unwrap().into_owned();
+            assert!(get("wide").as_f64().unwrap().abs() <= 1e308);
+            assert!(get("huge").as_u64().unwrap() > 18446744073709551000);
+            let top = get("top").as_u64().unwrap();
+            assert!(
+                (9223372036854775808..=9223372036854775900).contains(&top),
+                "{}",
+                top
+            );
+            let narrow = get("narrow").as_f32().unwrap();
+            assert!(narrow > 1.0 && narrow < 1.000_000_3, "{}", narrow);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_buf_validate_violations() -> Result<()> {
+        let mut loader = ProtoLoader::new();
+        loader.load_proto_file("proto/validated.proto")?;
+
+        let message_descriptor = loader.get_message_descriptor("validated.Account")?;
+        let mut faker = ProtoFaker::new(vec![])?.with_violation_rate(1.0);
+        faker.prepare(&loader, &message_descriptor)?;
+
+        for _ in 0..20 {
+            let message = faker.generate_dynamic(&loader, &message_descriptor)?;
+
+            let age = message.get_field_by_name("age").unwrap().as_i32().unwrap();
+            assert!(!(18..=119).contains(&age), "age {} should be invalid", age);
+
+            let visits = message.get_field_by_name("visits").unwrap();
+            assert!(visits.as_u64().unwrap() <= 50000);
+
+            let email = message.get_field_by_name("email").unwrap();
+            assert!(!email.as_str().unwrap().contains('@'));
+
+            assert!(!message.has_field_by_name("profile"));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_enum_options() -> Result<()> {
+        let mut loader = ProtoLoader::new();
+        loader.load_proto_file("proto/enums.proto")?;
+
+        let message_descriptor = loader.get_message_descriptor("enums.Order")?;
+        let mut faker = ProtoFaker::new(vec![])?;
+        faker.prepare(&loader, &message_descriptor)?;
+
+        for _ in 0..50 {
+            let message = faker.generate_dynamic(&loader, &message_descriptor)?;
+            let get = |name| {
+                message
+                    .get_field_by_name(name)
+                    .unwrap()
+                    .as_enum_number()
+                    .unwrap()
+            };
+
+            assert_ne!(get("status"), 0);
+            assert!([2, 3].contains(&get("picked")));
+            assert_eq!(get("weighted"), 1);
+            assert!(get("open") > 3);
+        }
+
+        // Nested message comments are honored too
+        let mut loader = ProtoLoader::new();
+        loader.load_proto_file("proto/person.proto")?;
+        let phone_descriptor = loader.get_message_descriptor("person.Person.PhoneNumber")?;
+        for _ in 0..20 {
+            let phone = faker.generate_dynamic(&loader, &phone_descriptor)?;
+            let phone_type = phone.get_field_by_name("type").unwrap();
+            assert_ne!(phone_type.as_enum_number(), Some(0));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_oneof_options() -> Result<()> {
+        let mut loader = ProtoLoader::new();
+        loader.load_proto_file("proto/oneofs.proto")?;
+
+        let message_descriptor = loader.get_message_descriptor("oneofs.Event")?;
+        let mut faker = ProtoFaker::new(vec![])?;
+        faker.prepare(&loader, &message_descriptor)?;
+
+        let rotation = ["first", "second", "third"];
+        for i in 0..9 {
+            let message = faker.generate_dynamic(&loader, &message_descriptor)?;
+
+            assert!(!message.has_field_by_name("a") && !message.has_field_by_name("b"));
+
+            for (j, name) in rotation.iter().enumerate() {
+                assert_eq!(message.has_field_by_name(name), i % 3 == j, "{}", name);
+            }
+
+            assert!(message.has_field_by_name("heavy"));
+            assert!(!message.has_field_by_name("light"));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_presence_and_sparsity() -> Result<()> {
+        let mut loader = ProtoLoader::new();
+        loader.load_proto_file("proto/sparse.proto")?;
+
+        let message_descriptor = loader.get_message_descriptor("sparse.Sparse")?;
+        let mut faker = ProtoFaker::new(vec![])?.with_sparsity(1.0);
+        faker.prepare(&loader, &message_descriptor)?;
+
+        for _ in 0..20 {
+            let message = faker.generate_dynamic(&loader, &message_descriptor)?;
+
+            for name in ["never", "maybe", "tags", "inner", "unannotated"] {
+                assert!(!message.has_field_by_name(name), "{} should be unset", name);
+            }
+            assert!(message.has_field_by_name("always"));
+        }
+
+        // Required fields are exempt from sparsity
+        let mut loader = ProtoLoader::new();
+        loader.load_proto_file("proto/validated.proto")?;
+        let account_descriptor = loader.get_message_descriptor("validated.Account")?;
+        let account = faker.generate_dynamic(&loader, &account_descriptor)?;
+        assert!(account.has_field_by_name("profile"));
+        assert!(!account.has_field_by_name("handle"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pools_for_all_kinds() -> Result<()> {
+        let mut loader = ProtoLoader::new();
+        loader.load_proto_file("proto/pools.proto")?;
+
+        let pool = |name: &str, value| PoolConfig {
+            name: String::from(name),
+            source: PoolSource::Random { items: 5, value },
+        };
+        let mut faker = ProtoFaker::new(vec![
+            pool("small", option_parser::ValueType::I32),
+            pool("counts", option_parser::ValueType::U32),
+            pool("scores", option_parser::ValueType::F32),
+            pool("blobs", option_parser::ValueType::Bytes),
+        ])?;
+
+        let message_descriptor = loader.get_message_descriptor("pools.Pooled")?;
+        faker.prepare(&loader, &message_descriptor)?;
+
+        let small = faker.pools["small"].clone();
+        for _ in 0..20 {
+            let message = faker.generate_dynamic(&loader, &message_descriptor)?;
+
+            let get = |name: &str| message.get_field_by_name(name).unwrap().into_owned();
+            assert!(small.contains(&get("small")));
+            assert!(small.contains(&get("hot")));
+            let Value::I64(wide) = get("wide") else {
+                panic!("wide should be widened to i64");
+            };
+            assert!(small.contains(&Value::I32(wide as i32)));
+            assert!(matches!(get("ratio"), Value::F64(_)));
+            assert!(matches!(get("total"), Value::U64(_)));
+            assert!(faker.pools["scores"].contains(&get("score")));
+            assert!(faker.pools["blobs"].contains(&get("blob")));
+            let Value::EnumNumber(level) = get("level") else {
+                panic!("level should be an enum number");
+            };
+            assert!(small.contains(&Value::I32(level)));
+        }
+
+        // Narrowing a signed pool into an unsigned field is refused up front
+        let mismatched = loader.get_message_descriptor("pools.Mismatched")?;
+        let err = faker.prepare(&loader, &mismatched).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<PoolError>(),
+            Some(PoolError::Incompatible { pool, .. }) if pool == "small"
+        ));
+
+        let err = ProtoFaker::new(vec![])?
+            .generate_dynamic(&loader, &message_descriptor)
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<PoolError>(),
+            Some(PoolError::NotFound { .. })
+        ));
+
+        Ok(())
+    }
 
     #[test]
-    fn test_generate_dynamic_message() -> Result<()> {
+    fn test_file_pools_are_checked_whole() -> Result<()> {
         let mut loader = ProtoLoader::new();
-        loader.load_proto_file("proto/person.proto")?;
+        loader.load_proto_file("proto/pools.proto")?;
+        let message_descriptor = loader.get_message_descriptor("pools.Loaded")?;
 
-        let message_descriptor = loader.get_message_descriptor("person.Person")?;
-        let faker = ProtoFaker::new(vec![PoolConfig {
-            name: String::from("user_id"),
-            items: 20,
-            value: option_parser::ValueType::Uuid,
-        }]);
+        let dir = tempfile::tempdir()?;
+        let faker = |ids: &str, levels: &str| -> Result<ProtoFaker> {
+            std::fs::write(dir.path().join("ids.jsonl"), ids)?;
+            std::fs::write(dir.path().join("levels.txt"), levels)?;
+            ProtoFaker::new(vec![
+                PoolConfig {
+                    name: String::from("ids"),
+                    source: PoolSource::Jsonl {
+                        path: dir.path().join("ids.jsonl"),
+                        field: None,
+                        value: None,
+                    },
+                },
+                PoolConfig {
+                    name: String::from("levels"),
+                    source: PoolSource::File {
+                        path: dir.path().join("levels.txt"),
+                        value: None,
+                    },
+                },
+            ])
+        };
 
-        let message = faker.generate_dynamic(&loader, &message_descriptor)?;
+        faker("1\n2\n3\n", "LOW\nLEVEL_UNSPECIFIED\n")?.prepare(&loader, &message_descriptor)?;
 
-        // Verify the message has data
-        assert!(message.get_field_by_name("name").is_some());
+        // A single stray value is refused up front, not only when it happens to be drawn
+        let err = faker("1\n2\n\"three\"\n", "LOW\n")?
+            .prepare(&loader, &message_descriptor)
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<PoolError>(),
+            Some(PoolError::Incompatible { pool, .. }) if pool == "ids"
+        ));
+        let err = faker("1\n", "LOW\nMISSING\n")?
+            .prepare(&loader, &message_descriptor)
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<PoolError>(),
+            Some(PoolError::Incompatible { pool, .. }) if pool == "levels"
+        ));
 
-        // Test serialization
-        let encoded = message.encode_to_vec();
-        assert!(!encoded.is_empty());
+        Ok(())
+    }
 
-        // Test deserialization
-        let decoded = DynamicMessage::decode(message_descriptor, encoded.as_slice())?;
+    #[test]
+    fn test_message_pools() -> Result<()> {
+        let mut loader = ProtoLoader::new();
+        loader.load_proto_file("proto/pools.proto")?;
 
-        // Verify some fields match
-        if let Some(name) = message.get_field_by_name("name") {
-            if let Some(decoded_name) = decoded.get_field_by_name("name") {
-                assert_eq!(name, decoded_name);
-            }
+        let mut faker = ProtoFaker::new(vec![PoolConfig {
+            name: String::from("devices"),
+            source: PoolSource::Message {
+                items: 4,
+                message_type: String::from("pools.Device"),
+            },
+        }])?;
+        let message_descriptor = loader.get_message_descriptor("pools.Event")?;
+        faker.prepare(&loader, &message_descriptor)?;
+
+        let devices = faker.pools["devices"].clone();
+        assert_eq!(devices.len(), 4);
+
+        for _ in 0..20 {
+            let message = faker.generate_dynamic(&loader, &message_descriptor)?;
+            let device = message.get_field_by_name("device").unwrap();
+            assert!(devices.contains(&device));
+
+            let seen = message.get_field_by_name("seen").unwrap();
+            let seen = seen.as_list().unwrap();
+            assert_eq!(seen.len(), 3);
+            assert!(seen.iter().all(|d| devices.contains(d)));
         }
 
+        // A pool of the wrong message type is rejected
+        let mut faker = ProtoFaker::new(vec![PoolConfig {
+            name: String::from("devices"),
+            source: PoolSource::Message {
+                items: 4,
+                message_type: String::from("pools.Sensor"),
+            },
+        }])?;
+        let err = faker.prepare(&loader, &message_descriptor).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<PoolError>(),
+            Some(PoolError::Incompatible { .. })
+        ));
+
         Ok(())
     }
 
     #[test]
-    fn test_generate_multiple_message_types() -> Result<()> {
+    fn test_sequences() -> Result<()> {
         let mut loader = ProtoLoader::new();
-        loader.load_proto_file("proto/person.proto")?;
+        loader.load_proto_file("proto/sequences.proto")?;
+        let message_descriptor = loader.get_message_descriptor("sequences.Order")?;
 
-        let faker = ProtoFaker::new(vec![PoolConfig {
-            name: String::from("user_id"),
-            items: 20,
-            value: option_parser::ValueType::Uuid,
-        }]);
+        let mut faker = ProtoFaker::new(vec![])?;
+        faker.prepare(&loader, &message_descriptor)?;
 
-        // Test Person message
-        let person_descriptor = loader.get_message_descriptor("person.Person")?;
-        let person = faker.generate_dynamic(&loader, &person_descriptor)?;
-        assert!(person.get_field_by_name("name").is_some());
+        for n in 0..5 {
+            let order = faker.generate_dynamic(&loader, &message_descriptor)?;
+            let get = |name: &str| order.get_field_by_name(name).unwrap().into_owned();
 
-        // Test PhoneNumber message
-        let phone_descriptor = loader.get_message_descriptor("person.Person.PhoneNumber")?;
-        let phone = faker.generate_dynamic(&loader, &phone_descriptor)?;
-        assert!(phone.get_field_by_name("number").is_some());
+            assert_eq!(get("id"), Value::I64(1000 + 5 * n));
+            assert_eq!(get("number"), Value::String(format!("ORD-{:06}", n + 1)));
+            assert_eq!(
+                get("positions"),
+                Value::List(vec![Value::I32(1), Value::I32(2), Value::I32(3)])
+            );
+
+            // Line numbers restart for every order
+            let items = get("items");
+            let lines: Vec<Value> = items
+                .as_list()
+                .unwrap()
+                .iter()
+                .map(|item| item.as_message().unwrap().get_field_by_name("line"))
+                .map(|line| line.unwrap().into_owned())
+                .collect();
+            let expected: Vec<Value> = (1..=lines.len() as u32).map(Value::U32).collect();
+            assert_eq!(lines, expected);
+        }
+
+        // Seeded runs number by message index, whichever order messages are generated in
+        let seeded = || -> Result<ProtoFaker> {
+            let mut faker = ProtoFaker::new(vec![])?.with_seed(7);
+            faker.prepare(&loader, &message_descriptor)?;
+            Ok(faker)
+        };
+        let (first, second) = (seeded()?, seeded()?);
+        let order = first.generate_indexed(&loader, &message_descriptor, 5)?;
+        assert_eq!(order.get_field_by_name("id").unwrap().as_i64(), Some(1025));
+        assert_eq!(
+            order.get_field_by_name("number").unwrap().as_str(),
+            Some("ORD-000006")
+        );
+        second.generate_indexed(&loader, &message_descriptor, 4)?;
+        assert_eq!(
+            order,
+            second.generate_indexed(&loader, &message_descriptor, 5)?
+        );
 
         Ok(())
     }
 
     #[test]
-    fn test_comment_attributes() -> Result<()> {
+    fn test_unique_values() -> Result<()> {
         let mut loader = ProtoLoader::new();
-        loader.load_proto_file("proto/person.proto")?;
+        loader.load_proto_file("proto/unique.proto")?;
+        let message_descriptor = loader.get_message_descriptor("unique.Account")?;
 
-        let message_descriptor = loader.get_message_descriptor("person.Person")?;
-        let faker = ProtoFaker::new(vec![PoolConfig {
-            name: String::from("user_id"),
-            items: 20,
-            value: option_parser::ValueType::Uuid,
-        }]);
+        let mut faker = ProtoFaker::new(vec![])?;
+        faker.prepare(&loader, &message_descriptor)?;
 
-        let message = faker.generate_dynamic(&loader, &message_descriptor)?;
+        let mut numbers = Vec::new();
+        let mut emails = HashSet::new();
+        for _ in 0..10 {
+            let account = faker.generate_dynamic(&loader, &message_descriptor)?;
+            numbers.push(
+                account
+                    .get_field_by_name("number")
+                    .unwrap()
+                    .as_i32()
+                    .unwrap(),
+            );
+            let email = account.get_field_by_name("email").unwrap();
+            assert!(emails.insert(email.as_str().unwrap().to_string()));
 
-        // Check that name has 1-3 words
-        if let Some(Cow::Borrowed(Value::String(name))) = message.get_field_by_name("name") {
-            let word_count = name.split_whitespace().count();
-            assert!(
-                (1..=3).contains(&word_count),
-                "Name '{}' should have 1-3 words, has {}",
-                name,
-                word_count
+            let picks = account.get_field_by_name("picks").unwrap();
+            let picks = picks.as_list().unwrap();
+            assert_eq!(picks.len(), 4);
+            for (i, pick) in picks.iter().enumerate() {
+                assert!(!picks[i + 1..].contains(pick), "{:?}", picks);
+            }
+        }
+        numbers.sort();
+        assert_eq!(numbers, (1..=10).collect::<Vec<_>>());
+
+        // Every number between min and max is used up
+        let err = faker
+            .generate_dynamic(&loader, &message_descriptor)
+            .unwrap_err();
+        assert!(err.to_string().contains("exhausted"), "{}", err);
+
+        // A bool list can't hold three distinct values
+        let crowded = loader.get_message_descriptor("unique.Crowded")?;
+        faker.prepare(&loader, &crowded)?;
+        let err = faker.generate_dynamic(&loader, &crowded).unwrap_err();
+        assert!(err.to_string().contains("unique.Crowded.flags"), "{}", err);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expr_fields() -> Result<()> {
+        let mut loader = ProtoLoader::new();
+        loader.load_proto_file("proto/derived.proto")?;
+        let message_descriptor = loader.get_message_descriptor("derived.Order")?;
+
+        let mut faker = ProtoFaker::new(vec![])?;
+        faker.prepare(&loader, &message_descriptor)?;
+
+        for _ in 0..10 {
+            let order = faker.generate_dynamic(&loader, &message_descriptor)?;
+            let get = |name: &str| order.get_field_by_name(name).unwrap().into_owned();
+
+            let price = get("price").as_f64().unwrap();
+            let quantity = get("quantity").as_i32().unwrap();
+            let total = get("total").as_f64().unwrap();
+            assert_eq!(total, price * quantity as f64);
+            let shipping = get("shipping").as_f64().unwrap();
+            assert_eq!(get("grand_total").as_f64(), Some(total + shipping));
+
+            let customer = get("customer");
+            let first_name = customer
+                .as_message()
+                .unwrap()
+                .get_field_by_name("first_name");
+            assert_eq!(
+                get("email").as_str().unwrap(),
+                format!(
+                    "{}@example.com",
+                    first_name.unwrap().as_str().unwrap().to_lowercase()
+                )
             );
-        } else {
-            panic!("No Name Field");
+
+            let seconds = |name: &str| {
+                let timestamp = get(name);
+                let timestamp = timestamp.as_message().unwrap();
+                timestamp
+                    .get_field_by_name("seconds")
+                    .unwrap()
+                    .as_i64()
+                    .unwrap()
+            };
+            assert!((0..=3600).contains(&(seconds("updated_at") - seconds("created_at"))));
+
+            for line in get("lines").as_list().unwrap() {
+                let line = line.as_message().unwrap();
+                let line_quantity = line.get_field_by_name("line_quantity").unwrap();
+                assert_eq!(line_quantity.as_i32(), Some(quantity * 2));
+            }
         }
 
-        // Check that uuid is a valid UUID
-        if let Some(Cow::Borrowed(Value::String(uuid_str))) = message.get_field_by_name("uuid") {
-            assert!(
-                Uuid::parse_str(uuid_str).is_ok(),
-                "Invalid UUID: {}",
-                uuid_str
+        let cyclic = loader.get_message_descriptor("derived.Cyclic")?;
+        let err = faker.prepare(&loader, &cyclic).unwrap_err();
+        assert!(err.to_string().contains("a -> b -> a"), "{}", err);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_templates() -> Result<()> {
+        let mut loader = ProtoLoader::new();
+        loader.load_proto_file("proto/templates.proto")?;
+        let message_descriptor = loader.get_message_descriptor("templates.Order")?;
+
+        let mut faker = ProtoFaker::new(vec![PoolConfig {
+            name: String::from("regions"),
+            source: PoolSource::Random {
+                items: 3,
+                value: option_parser::ValueType::String,
+            },
+        }])?;
+        faker.prepare(&loader, &message_descriptor)?;
+
+        for number in 1000..1005 {
+            let order = faker.generate_dynamic(&loader, &message_descriptor)?;
+            let get = |name: &str| order.get_field_by_name(name).unwrap().into_owned();
+
+            let region = get("region").as_str().unwrap().to_string();
+            let id = get("id").as_str().unwrap().to_string();
+            let (prefix, pool_value) = id.split_once('@').unwrap();
+            assert_eq!(&prefix[..11], format!("ORD-{:06}-", number));
+            assert!(prefix[11..].chars().all(|c| c.is_ascii_uppercase()));
+            assert_eq!(prefix.len(), 14);
+            assert!(faker.pools["regions"].contains(&Value::String(pool_value.to_string())));
+            assert!(faker.pools["regions"].contains(&Value::String(region)));
+
+            let customer = get("customer").as_str().unwrap().to_lowercase();
+            let email = get("email").as_str().unwrap().to_string();
+            let local = email.strip_suffix("@example.com").unwrap();
+            assert_eq!(&local[..customer.len()], customer);
+
+            for (i, line) in get("lines").as_list().unwrap().iter().enumerate() {
+                let line = line.as_message().unwrap();
+                let line_id = line.get_field_by_name("id").unwrap();
+                assert_eq!(line_id.as_str().unwrap(), format!("{}/{}", id, i + 1));
+            }
+        }
+
+        let forward = loader.get_message_descriptor("templates.Forward")?;
+        let err = faker.prepare(&loader, &forward).unwrap_err();
+        assert!(
+            err.to_string().contains("isn't generated before it"),
+            "{}",
+            err
+        );
+
+        // Every pool value is checked, not just one picked at random
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("regions.jsonl");
+        std::fs::write(&path, "\"eu\"\n\"us\"\n\"ap\"\n18446744073709551615\n")?;
+        let mut faker = ProtoFaker::new(vec![PoolConfig {
+            name: String::from("regions"),
+            source: PoolSource::Jsonl {
+                path,
+                field: None,
+                value: None,
+            },
+        }])?;
+        let tagged = loader.get_message_descriptor("templates.Tagged")?;
+        let err = faker.prepare(&loader, &tagged).unwrap_err();
+        assert!(
+            err.to_string().contains("can't be used in a template"),
+            "{}",
+            err
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_locales() -> Result<()> {
+        let mut loader = ProtoLoader::new();
+        loader.load_proto_file("proto/locales.proto")?;
+        let message_descriptor = loader.get_message_descriptor("locales.Person")?;
+
+        let mut faker =
+            ProtoFaker::new(vec![])?.with_locales(LocaleMix::parse("en:0.5,ja_JP:0.5")?);
+        faker.prepare(&loader, &message_descriptor)?;
+
+        let mut japanese = 0;
+        for _ in 0..40 {
+            let person = faker.generate_dynamic(&loader, &message_descriptor)?;
+            let first_name = person.get_field_by_name("first_name").unwrap();
+            let address = person.get_field_by_name("address").unwrap();
+            let street = address.as_message().unwrap().get_field_by_name("street");
+
+            // One locale for the whole message, nested messages included
+            let ascii = first_name.as_str().unwrap().is_ascii();
+            assert_eq!(street.unwrap().as_str().unwrap().is_ascii(), ascii);
+            if !ascii {
+                japanese += 1;
+            }
+
+            let nickname = person.get_field_by_name("nickname").unwrap();
+            assert!(!nickname.as_str().unwrap().is_ascii());
+        }
+        assert!((5..=35).contains(&japanese), "{}", japanese);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_inference() -> Result<()> {
+        let mut loader = ProtoLoader::new();
+        loader.load_proto_file("proto/inference.proto")?;
+        let message_descriptor = loader.get_message_descriptor("inference.Account")?;
+
+        let mut faker = ProtoFaker::new(vec![])?;
+        faker.prepare(&loader, &message_descriptor)?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+
+        for _ in 0..20 {
+            let account = faker.generate_dynamic(&loader, &message_descriptor)?;
+            let field = |name| account.get_field_by_name(name).unwrap().into_owned();
+
+            assert!(Uuid::parse_str(field("id").as_str().unwrap()).is_ok());
+            assert!(field("contact_email").as_str().unwrap().contains('@'));
+            let created_at = field("created_at").as_i64().unwrap();
+            assert!((created_at / 1000 - now).abs() <= 86400, "{}", created_at);
+            let login_time = field("login_time").as_u32().unwrap() as i64;
+            assert!((login_time - now).abs() <= 86400, "{}", login_time);
+            assert!((-90.0..=90.0).contains(&field("latitude").as_f64().unwrap()));
+            assert!(field("item_count").as_i32().unwrap() >= 0);
+
+            // Fields with their own options keep them
+            let price = field("price_cents").as_i32().unwrap();
+            assert!((-1000..=50).contains(&price), "{}", price);
+            let display_name = field("display_name");
+            assert_eq!(display_name.as_str().unwrap().len(), 4);
+            assert_eq!(field("notes").as_str().unwrap(), "ok");
+        }
+
+        let faker = ProtoFaker::new(vec![])?.with_inference(Inference::parse(
+            "defaults = false\n[[rules]]\nnames = [\"*_count\"]\nkinds = [\"integer\"]\noptions = \"min=7 max=7\"",
+        )?);
+        let account = faker.generate_dynamic(&loader, &message_descriptor)?;
+        assert_eq!(
+            account.get_field_by_name("item_count").unwrap().as_i32(),
+            Some(7)
+        );
+        let city = account.get_field_by_name("city").unwrap();
+        assert!(!city.as_str().unwrap().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bytes() -> Result<()> {
+        let mut loader = ProtoLoader::new();
+        loader.load_proto_file("proto/binary.proto")?;
+        let message_descriptor = loader.get_message_descriptor("binary.Blob")?;
+
+        let mut faker = ProtoFaker::new(vec![PoolConfig {
+            name: "tokens".to_string(),
+            source: PoolSource::Random {
+                items: 3,
+                value: option_parser::ValueType::String,
+            },
+        }])?;
+        faker.prepare(&loader, &message_descriptor)?;
+        let tokens: Vec<Vec<u8>> = faker.pools["tokens"]
+            .iter()
+            .map(|token| token.as_str().unwrap().as_bytes().to_vec())
+            .collect();
+
+        for _ in 0..20 {
+            let blob = faker.generate_dynamic(&loader, &message_descriptor)?;
+            let bytes = |name| {
+                blob.get_field_by_name(name)
+                    .unwrap()
+                    .as_bytes()
+                    .unwrap()
+                    .to_vec()
+            };
+
+            assert_eq!(bytes("raw").len(), 8);
+            let note = String::from_utf8(bytes("note"))?;
+            assert!((3..=5).contains(&note.len()), "{:?}", note);
+            assert_eq!(Uuid::from_slice(&bytes("id"))?.get_version_num(), 4);
+            assert_eq!(bytes("digest").len(), 32);
+            assert!(bytes("avatar").starts_with(b"\x89PNG"));
+            assert!(tokens.contains(&bytes("token")));
+            let code = blob.get_field_by_name("code").unwrap();
+            assert_eq!(code.as_str().unwrap().chars().count(), 3);
+        }
+
+        let bad = loader.get_message_descriptor("binary.BadLength")?;
+        let err = ProtoFaker::new(vec![])?.prepare(&loader, &bad).unwrap_err();
+        assert!(err.to_string().contains("len only applies"), "{}", err);
+
+        let mut faker = ProtoFaker::new(vec![])?;
+        assert!(faker.prepare(&loader, &message_descriptor).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_embedded_messages() -> Result<()> {
+        let mut loader = ProtoLoader::new();
+        loader.load_proto_file("proto/envelopes.proto")?;
+        let click = loader.get_message_descriptor("envelopes.Click")?;
+        let purchase = loader.get_message_descriptor("envelopes.Purchase")?;
+
+        let decode = |type_name: &str, payload: &[u8]| -> Result<DynamicMessage> {
+            let descriptor = match type_name.rsplit('/').next() {
+                Some("envelopes.Click") => click.clone(),
+                Some("envelopes.Purchase") => purchase.clone(),
+                name => panic!("unexpected type {:?}", name),
+            };
+            Ok(DynamicMessage::decode(descriptor, payload)?)
+        };
+
+        for message_type in ["envelopes.Envelope", "envelopes.Routed"] {
+            let message_descriptor = loader.get_message_descriptor(message_type)?;
+            let mut faker = ProtoFaker::new(vec![])?;
+            faker.prepare(&loader, &message_descriptor)?;
+
+            let mut types = HashSet::new();
+            for _ in 0..30 {
+                let message = faker.generate_dynamic(&loader, &message_descriptor)?;
+                let fields: Vec<_> = message.fields().map(|(_, value)| value.clone()).collect();
+                let type_name = fields[0].as_str().unwrap();
+                let inner = decode(type_name, fields[1].as_bytes().unwrap())?;
+                if let Some(quantity) = inner.get_field_by_name("quantity") {
+                    assert!((1..=9).contains(&quantity.as_i32().unwrap()));
+                }
+                types.insert(inner.descriptor().full_name().to_string());
+            }
+            assert_eq!(types.len(), 2, "{}", message_type);
+        }
+
+        let broken = loader.get_message_descriptor("envelopes.Broken")?;
+        let err = ProtoFaker::new(vec![])?
+            .prepare(&loader, &broken)
+            .unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("envelopes.Missing"),
+            "{:#}",
+            err
+        );
+
+        // The types a sibling may name are declared up front, and nothing else is accepted
+        let undeclared = loader.get_message_descriptor("envelopes.Undeclared")?;
+        let err = ProtoFaker::new(vec![])?
+            .prepare(&loader, &undeclared)
+            .unwrap_err();
+        assert!(err.to_string().contains("bytes_of_types"), "{}", err);
+
+        let misrouted = loader.get_message_descriptor("envelopes.Misrouted")?;
+        let mut faker = ProtoFaker::new(vec![])?;
+        faker.prepare(&loader, &misrouted)?;
+        let err = faker.generate_dynamic(&loader, &misrouted).unwrap_err();
+        assert!(err.to_string().contains("envelopes.Purchase"), "{}", err);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_overlay() -> Result<()> {
+        let mut loader = ProtoLoader::new();
+        loader.load_proto_file("proto/person.proto")?;
+        let message_descriptor = loader.get_message_descriptor("person.Person")?;
+
+        let mut overlay = Overlay::parse(
+            r#"
+            [fields]
+            "person.Person.name" = 'pattern="Ada"'
+            "person.Person.phones[].number" = 'pattern="[0-9]{6}"'
+            "person.Person.user_id" = "pool=ids"
+            "person.Engineer.age" = 'expr="30"'
+            "#,
+            &loader,
+        )?;
+        // Replaces the comment's pattern, which would otherwise be tried first
+        overlay.set(&loader, &message_descriptor, "ticket=string=uuid")?;
+        let mut faker = ProtoFaker::new(vec![PoolConfig {
+            name: "ids".to_string(),
+            source: PoolSource::Random {
+                items: 2,
+                value: option_parser::ValueType::Uuid,
+            },
+        }])?
+        .with_overlay(overlay);
+        faker.prepare(&loader, &message_descriptor)?;
+
+        for _ in 0..10 {
+            let person = faker.generate_dynamic(&loader, &message_descriptor)?;
+            assert_eq!(
+                person.get_field_by_name("name").unwrap().as_str(),
+                Some("Ada")
             );
-        } else {
-            panic!("No UUID");
+            for phone in person
+                .get_field_by_name("phones")
+                .unwrap()
+                .as_list()
+                .unwrap()
+            {
+                let number = phone
+                    .as_message()
+                    .unwrap()
+                    .get_field_by_name("number")
+                    .unwrap();
+                let number = number.as_str().unwrap();
+                assert!(number.len() == 6 && number.chars().all(|c| c.is_ascii_digit()));
+            }
+            let user_id = person.get_field_by_name("user_id").unwrap();
+            assert!(Uuid::parse_str(user_id.as_str().unwrap()).is_ok());
+            let ticket = person.get_field_by_name("ticket").unwrap();
+            assert!(Uuid::parse_str(ticket.as_str().unwrap()).is_ok());
+            if person.has_field_by_name("engineer") {
+                let engineer = person.get_field_by_name("engineer").unwrap();
+                let age = engineer.as_message().unwrap().get_field_by_name("age");
+                assert_eq!(age.unwrap().as_i32(), Some(30));
+            }
         }
 
-        // Check that phones has 1-3 entries
-        if let Some(Cow::Borrowed(Value::List(phones))) = message.get_field_by_name("phones") {
+        // Values of the wrong type are rejected up front
+        for set in ["phones=count=1.5", "phones=count=5..", "name=words=2.5"] {
+            let mut overlay = Overlay::default();
+            overlay.set(&loader, &message_descriptor, set)?;
+            let mut faker = ProtoFaker::new(vec![PoolConfig {
+                name: "user_id".to_string(),
+                source: PoolSource::Random {
+                    items: 2,
+                    value: option_parser::ValueType::Uuid,
+                },
+            }])?
+            .with_overlay(overlay);
+            let err = faker.prepare(&loader, &message_descriptor).unwrap_err();
+            let field = set.split('=').next().unwrap();
             assert!(
-                !phones.is_empty() && phones.len() <= 3,
-                "Phones should have 1-3 entries, has {}",
-                phones.len()
+                err.to_string()
+                    .contains(&format!("person.Person.{}", field)),
+                "{}",
+                err
             );
-        } else {
-            panic!("No phones");
         }
 
         Ok(())
     }
 
     #[test]
-    fn test_oneof_fields() -> Result<()> {
+    fn test_message_defaults() -> Result<()> {
         let mut loader = ProtoLoader::new();
-        loader.load_proto_file("proto/person.proto")?;
+        loader.load_proto_file("proto/defaults.proto")?;
+        let profile = loader.get_message_descriptor("defaults.Profile")?;
+        let plain = loader.get_message_descriptor("defaults.Plain")?;
 
-        let message_descriptor = loader.get_message_descriptor("person.Person")?;
-        let faker = ProtoFaker::new(vec![PoolConfig {
-            name: String::from("user_id"),
-            items: 20,
-            value: option_parser::ValueType::Uuid,
-        }]);
+        let mut faker = ProtoFaker::new(vec![])?;
+        faker.prepare(&loader, &profile)?;
+        faker.prepare(&loader, &plain)?;
 
-        // Generate multiple messages to ensure oneof fields are properly handled
-        for _ in 0..10 {
-            let message = faker.generate_dynamic(&loader, &message_descriptor)?;
+        let mut bios = 0;
+        for _ in 0..40 {
+            let message = faker.generate_dynamic(&loader, &profile)?;
+            if message.has_field_by_name("bio") {
+                assert_eq!(
+                    message.get_field_by_name("bio").unwrap().as_str(),
+                    Some("x")
+                );
+                bios += 1;
+            }
+            assert_eq!(
+                message.get_field_by_name("nickname").unwrap().as_str(),
+                Some("x")
+            );
+            let tags = message.get_field_by_name("tags").unwrap();
+            assert!(tags.as_list().unwrap().len() <= 2);
 
-            // Check that exactly one of the oneof fields is set
-            let engineer = message.get_field_by_name("engineer");
-            let manager = message.get_field_by_name("manager");
+            // Cascaded defaults reach nested messages, under their own
+            let details = message.get_field_by_name("details").unwrap();
+            let details = details.as_message().unwrap();
+            if details.has_field_by_name("note") {
+                let note = details.get_field_by_name("note").unwrap();
+                assert_eq!(note.as_str(), Some("own"));
+            }
+            let level = details
+                .get_field_by_name("level")
+                .unwrap()
+                .as_i64()
+                .unwrap();
+            assert!((1..=3).contains(&level), "{}", level);
+            if details.has_field_by_name("label") {
+                assert_eq!(
+                    details.get_field_by_name("label").unwrap().as_str(),
+                    Some("x")
+                );
+            }
 
-            let engineer = engineer.unwrap();
-            let manager = manager.unwrap();
-            let e_age = engineer
+            let message = faker.generate_dynamic(&loader, &plain)?;
+            let details = message.get_field_by_name("details").unwrap();
+            let label = details
                 .as_message()
                 .unwrap()
-                .get_field_by_name("age")
-                .unwrap()
-                .as_i32()
+                .get_field_by_name("label")
                 .unwrap();
-            let m_age = manager
+            assert_ne!(label.as_str(), Some("x"));
+            let level = details
                 .as_message()
                 .unwrap()
-                .get_field_by_name("age")
+                .get_field_by_name("level")
+                .unwrap();
+            assert!((1..=3).contains(&level.as_i64().unwrap()));
+        }
+        assert!((5..=35).contains(&bios), "{}", bios);
+
+        let bad = loader.get_message_descriptor("defaults.Bad")?;
+        assert!(ProtoFaker::new(vec![])?.prepare(&loader, &bad).is_err());
+
+        // A pool is typed, so it's for single fields only
+        let shared = loader.get_message_descriptor("defaults.Shared")?;
+        let err = ProtoFaker::new(vec![])?
+            .prepare(&loader, &shared)
+            .unwrap_err();
+        assert!(err.to_string().contains("single field"), "{}", err);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_distributions() -> Result<()> {
+        let mut loader = ProtoLoader::new();
+        loader.load_proto_file("proto/distributions.proto")?;
+        let reading = loader.get_message_descriptor("distributions.Reading")?;
+
+        let mut faker = ProtoFaker::new(vec![])?;
+        faker.prepare(&loader, &reading)?;
+
+        let mut healthy = 0;
+        for _ in 0..200 {
+            let message = faker.generate_dynamic(&loader, &reading)?;
+            let field = |name| message.get_field_by_name(name).unwrap();
+            if field("healthy").as_bool().unwrap() {
+                healthy += 1;
+            }
+            assert_eq!(field("level").as_i32(), Some(3));
+            assert!((0.0..=60.0).contains(&field("wait").as_f64().unwrap()));
+            assert!((1..=100).contains(&field("rank").as_i64().unwrap()));
+            assert!(field("load").as_u32().unwrap() <= 10);
+        }
+        assert!((150..=199).contains(&healthy), "{}", healthy);
+
+        let mut latency = 0.0;
+        for _ in 0..500 {
+            let message = faker.generate_dynamic(&loader, &reading)?;
+            latency += message
+                .get_field_by_name("latency")
+                .unwrap()
+                .as_f64()
+                .unwrap();
+            let score = message
+                .get_field_by_name("score")
                 .unwrap()
                 .as_i32()
                 .unwrap();
+            assert!((0..=100).contains(&score), "{}", score);
+        }
+        // Sampled values keep the distribution's mean
+        assert!(
+            (45.0..=55.0).contains(&(latency / 500.0)),
+            "{}",
+            latency / 500.0
+        );
 
-            // Either engineer or manager should be set, but not both
+        // Without a `count` or `max_len`, heavy tails are capped
+        let heavy = loader.get_message_descriptor("distributions.Heavy")?;
+        faker.prepare(&loader, &heavy)?;
+        for _ in 0..5 {
+            let message = faker.generate_dynamic(&loader, &heavy)?;
+            let items = message.get_field_by_name("items").unwrap();
+            assert_eq!(items.as_list().unwrap().len(), 1000);
+            let note = message.get_field_by_name("note").unwrap();
+            assert_eq!(note.as_str().unwrap().chars().count(), 65536);
+        }
+
+        for bad in ["Bad", "BadMode", "BadPool"] {
+            let bad = loader.get_message_descriptor(&format!("distributions.{}", bad))?;
+            let mut faker = ProtoFaker::new(vec![PoolConfig {
+                name: String::from("ids"),
+                source: PoolSource::Random {
+                    items: 10,
+                    value: option_parser::ValueType::I32,
+                },
+            }])?;
+            let error = faker.prepare(&loader, &bad).unwrap_err();
+            assert!(error.to_string().contains("distribution"), "{}", error);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_empirical_distributions() -> Result<()> {
+        let mut loader = ProtoLoader::new();
+        loader.load_proto_file("proto/empirical.proto")?;
+        let upload = loader.get_message_descriptor("empirical.Upload")?;
+
+        let dir = tempfile::tempdir()?;
+        let sizes = dir.path().join("payload sizes.csv");
+        std::fs::write(&sizes, "size,weight\n16,5\n64,0\n256,2\n")?;
+        let items = dir.path().join("items.csv");
+        std::fs::write(&items, "items\n1\n2\n3\n")?;
+        let (sizes, items) = (sizes.display(), items.display());
+
+        let mut overlay = Overlay::default();
+        for spec in [
+            format!("size.distribution=empirical(\"{}\")", sizes),
+            format!("items.count_distribution=empirical({})", items),
+            format!("name.len_distribution=empirical({})", items),
+            format!(
+                "body=\"len_distribution=empirical(\"{}\") max_len=100\"",
+                sizes
+            ),
+            format!(
+                "latency=\"distribution=empirical({}) distribution_mode=bias max=10\"",
+                items
+            ),
+            // With a pool the distribution biases picks, as other distributions do
+            format!("pick=\"pool=ids distribution=empirical({})\"", items),
+        ] {
+            overlay.set(&loader, &upload, &spec)?;
+        }
+        let mut faker = ProtoFaker::new(vec![PoolConfig {
+            name: String::from("ids"),
+            source: PoolSource::Random {
+                items: 5,
+                value: option_parser::ValueType::I32,
+            },
+        }])?
+        .with_overlay(overlay);
+        faker.prepare(&loader, &upload)?;
+
+        for _ in 0..50 {
+            let message = faker.generate_dynamic(&loader, &upload)?;
+            let field = |name| message.get_field_by_name(name).unwrap();
+            // Samples are values, from the buckets with weight
+            let size = field("size").as_i64().unwrap();
             assert!(
-                (e_age != 0) ^ (m_age != 0), // XOR operator ensures exactly one is true
-                "Oneof field should have exactly one value set"
+                (0..=16).contains(&size) || (64..=256).contains(&size),
+                "{}",
+                size
             );
+            let items = field("items").as_list().unwrap().len();
+            assert!((1..=3).contains(&items), "{}", items);
+            // Length options truncate the distribution
+            let name = field("name").as_str().unwrap().chars().count();
+            assert!((2..=3).contains(&name), "{}", name);
+            let body = field("body").as_bytes().unwrap().len();
+            assert!(body <= 100, "{}", body);
+            assert!((-1000.0..=10.0).contains(&field("latency").as_f64().unwrap()));
+            assert!(faker.pools["ids"].contains(&field("pick")));
+        }
+
+        let mut overlay = Overlay::default();
+        overlay.set(&loader, &upload, "size.distribution=empirical(missing.csv)")?;
+        let error = ProtoFaker::new(vec![])?
+            .with_overlay(overlay)
+            .prepare(&loader, &upload)
+            .unwrap_err();
+        assert!(
+            format!("{:#}", error).contains("missing.csv"),
+            "{:#}",
+            error
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sub_options() -> Result<()> {
+        let mut loader = ProtoLoader::new();
+        loader.load_proto_file("proto/shared.proto")?;
+        let order = loader.get_message_descriptor("shared.Order")?;
+
+        let mut faker = ProtoFaker::new(vec![])?;
+        faker.prepare(&loader, &order)?;
+
+        let amount = |message: &DynamicMessage, field: &str| {
+            let money = message.get_field_by_name(field).unwrap();
+            let money = money.as_message().unwrap();
+            money.get_field_by_name("amount").unwrap().as_i64().unwrap()
+        };
+        for _ in 0..20 {
+            let message = faker.generate_dynamic(&loader, &order)?;
+            assert!((1000..=2000).contains(&amount(&message, "price")));
+            assert!((0..=50).contains(&amount(&message, "refund")));
+
+            // Paths reach through nested messages, and the path's options beat the field's
+            let lines = message.get_field_by_name("lines").unwrap();
+            let lines = lines.as_list().unwrap();
+            assert_eq!(lines.len(), 2);
+            for line in lines {
+                assert_eq!(amount(line.as_message().unwrap(), "price"), 7);
+            }
         }
 
+        let bad = loader.get_message_descriptor("shared.Bad")?;
+        assert!(ProtoFaker::new(vec![])?.prepare(&loader, &bad).is_err());
+        let derived = loader.get_message_descriptor("shared.Derived")?;
+        let err = ProtoFaker::new(vec![])?
+            .prepare(&loader, &derived)
+            .unwrap_err();
+        assert!(err.to_string().contains("expr"), "{}", err);
+
+        // An overlay or --set still has the last word
+        let mut overlay = Overlay::default();
+        overlay.set(&loader, &order, "shared.Money.amount=min=9 max=9")?;
+        let mut faker = ProtoFaker::new(vec![])?.with_overlay(overlay);
+        faker.prepare(&loader, &order)?;
+        let message = faker.generate_dynamic(&loader, &order)?;
+        assert_eq!(amount(&message, "refund"), 9);
+
         Ok(())
     }
 }
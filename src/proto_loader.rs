@@ -69,55 +69,34 @@ impl ProtoLoader {
         self.pool.encode_to_vec()
     }
 
-    pub fn get_comment(
-        &self,
-        file_name: &str,
-        message_name: &str,
-        field_name: &str,
-    ) -> Result<Option<String>> {
-        match self.get_file_descriptor_proto(file_name) {
-            Ok(file) => {
-                if let Some(message) = file.message_type.iter().find(|m| m.name() == message_name) {
-                    if let Some(field_index) =
-                        message.field.iter().position(|f| f.name() == field_name)
-                    {
-                        if let Some(message_index) = file
-                            .message_type
-                            .iter()
-                            .position(|m| m.name() == message_name)
-                        {
-                            // Build the expected path to the field:
-                            // [4, message_index, 2, field_index]
-                            // (we assume top-level message)
-                            let path = vec![4, message_index as i32, 2, field_index as i32];
-
-                            if let Some(source_code_info) = file.source_code_info {
-                                for location in source_code_info.location.iter() {
-                                    if location.path == path {
-                                        return Ok(location.leading_comments.as_ref().map_or_else(
-                                            || location.trailing_comments.clone(),
-                                            |lead| {
-                                                location.trailing_comments.as_ref().map_or(
-                                                    Some(lead.clone()),
-                                                    |trail| {
-                                                        Some(
-                                                            [lead.to_string(), trail.to_string()]
-                                                                .join(" "),
-                                                        )
-                                                    },
-                                                )
-                                            },
-                                        ));
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-
-                Ok(None)
+    /// Get the comments attached to the element at `path` in a file's source info.
+    ///
+    /// Paths come from descriptors (e.g. `FieldDescriptor::path`), so nested messages,
+    /// oneofs and message declarations can all be looked up. Leading and trailing
+    /// comments are joined with a space.
+    pub fn get_comment(&self, file_name: &str, path: &[i32]) -> Result<Option<String>> {
+        let file = self.get_file_descriptor_proto(file_name)?;
+
+        let Some(source_code_info) = file.source_code_info else {
+            return Ok(None);
+        };
+
+        for location in source_code_info.location.iter() {
+            if location.path == path {
+                return Ok(location.leading_comments.as_ref().map_or_else(
+                    || location.trailing_comments.clone(),
+                    |lead| {
+                        location
+                            .trailing_comments
+                            .as_ref()
+                            .map_or(Some(lead.clone()), |trail| {
+                                Some([lead.to_string(), trail.to_string()].join(" "))
+                            })
+                    },
+                ));
             }
-            Err(e) => Err(e),
         }
+
+        Ok(None)
     }
 }
@@ -0,0 +1,182 @@
+use anyhow::{Result, anyhow, bail};
+use prost_reflect::{FieldDescriptor, Kind, Value};
+use std::collections::HashMap;
+
+use crate::option_parser;
+
+/// Which generated values share a sequence's counter
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scope {
+    /// One counter for the whole run
+    Run,
+    /// A counter per parent message, e.g. line item numbers restarting for every order
+    Parent,
+}
+
+/// A monotonic sequence for an integer or string field, built from its `sequence`, `step`,
+/// `scope` and `format` options.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sequence {
+    start: i64,
+    step: i64,
+    pub scope: Scope,
+    format: Option<String>,
+}
+
+/// Counts 1, 2, 3... for the whole run
+impl Default for Sequence {
+    fn default() -> Self {
+        Sequence {
+            start: 1,
+            step: 1,
+            scope: Scope::Run,
+            format: None,
+        }
+    }
+}
+
+impl Sequence {
+    /// The sequence for a field, or `None` if it has no `sequence` option
+    pub fn from_options(
+        field: &FieldDescriptor,
+        options: &HashMap<String, option_parser::Value>,
+    ) -> Result<Option<Self>> {
+        let start = match options.get("sequence") {
+            Some(option_parser::Value::RangeFrom(start) | option_parser::Value::Int(start)) => {
+                *start
+            }
+            Some(option_parser::Value::Bool(true)) => 1,
+            Some(option_parser::Value::Bool(false)) | None => return Ok(None),
+            Some(sequence) => bail!("sequence must be a start like 1000.., got {:?}", sequence),
+        };
+
+        let step = match options.get("step") {
+            Some(option_parser::Value::Int(0)) => bail!("step must not be 0"),
+            Some(option_parser::Value::Int(step)) => *step,
+            Some(step) => bail!("step must be an integer, got {:?}", step),
+            None => 1,
+        };
+
+        let scope = match options.get("scope") {
+            Some(option_parser::Value::Str(scope)) if scope == "run" => Scope::Run,
+            Some(option_parser::Value::Str(scope)) if scope == "parent" => Scope::Parent,
+            Some(scope) => bail!("Unknown sequence scope {:?}, expected run or parent", scope),
+            None => Scope::Run,
+        };
+
+        let format = match (options.get("format"), field.kind()) {
+            (Some(option_parser::Value::Str(format)), Kind::String) => {
+                format_number(format, start)?;
+                Some(format.clone())
+            }
+            (Some(_), Kind::String) => bail!("format must be a quoted string like \"ORD-{{:06}}\""),
+            (Some(_), _) => bail!("format only applies to string fields"),
+            (None, _) => None,
+        };
+
+        match field.kind() {
+            Kind::Int32
+            | Kind::Sint32
+            | Kind::Sfixed32
+            | Kind::Int64
+            | Kind::Sint64
+            | Kind::Sfixed64
+            | Kind::Uint32
+            | Kind::Fixed32
+            | Kind::Uint64
+            | Kind::Fixed64
+            | Kind::String => {}
+            kind => bail!(
+                "sequence only applies to integer and string fields, not {:?}",
+                kind
+            ),
+        }
+
+        Ok(Some(Sequence {
+            start,
+            step,
+            scope,
+            format,
+        }))
+    }
+
+    /// The `position`th (from 0) number of the sequence
+    pub fn number(&self, position: u64) -> Result<i64> {
+        i64::try_from(position)
+            .ok()
+            .and_then(|position| self.step.checked_mul(position))
+            .and_then(|offset| self.start.checked_add(offset))
+            .ok_or_else(|| anyhow!("sequence overflowed after {} values", position))
+    }
+
+    /// The `position`th (from 0) value of the sequence, as the field's type
+    pub fn value(&self, position: u64, field: &FieldDescriptor) -> Result<Value> {
+        let number = self.number(position)?;
+        let out_of_range = || anyhow!("sequence value {} doesn't fit the field", number);
+
+        match field.kind() {
+            Kind::Int32 | Kind::Sint32 | Kind::Sfixed32 => Ok(Value::I32(
+                i32::try_from(number).map_err(|_| out_of_range())?,
+            )),
+            Kind::Int64 | Kind::Sint64 | Kind::Sfixed64 => Ok(Value::I64(number)),
+            Kind::Uint32 | Kind::Fixed32 => Ok(Value::U32(
+                u32::try_from(number).map_err(|_| out_of_range())?,
+            )),
+            Kind::Uint64 | Kind::Fixed64 => Ok(Value::U64(
+                u64::try_from(number).map_err(|_| out_of_range())?,
+            )),
+            Kind::String => match &self.format {
+                Some(format) => Ok(Value::String(format_number(format, number)?)),
+                None => Ok(Value::String(number.to_string())),
+            },
+            kind => bail!(
+                "sequence only applies to integer and string fields, not {:?}",
+                kind
+            ),
+        }
+    }
+}
+
+/// Put a number into the first `{}` placeholder of a format. `{:06}` zero pads to 6
+/// digits and `{:6}` pads with spaces.
+pub fn format_number(format: &str, number: i64) -> Result<String> {
+    let (start, end) = format
+        .find('{')
+        .and_then(|start| Some((start, start + format[start..].find('}')?)))
+        .ok_or_else(|| anyhow!("format {:?} has no {{}} placeholder", format))?;
+
+    let formatted = match &format[start + 1..end] {
+        "" => number.to_string(),
+        spec => {
+            let width = spec
+                .strip_prefix(':')
+                .ok_or_else(|| anyhow!("Unsupported placeholder {{{}}}", spec))?;
+            match width.strip_prefix('0') {
+                Some(width) => format!("{:0width$}", number, width = width.parse()?),
+                None => format!("{:width$}", number, width = width.parse()?),
+            }
+        }
+    };
+
+    Ok(format!(
+        "{}{}{}",
+        &format[..start],
+        formatted,
+        &format[end + 1..]
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_number() -> Result<()> {
+        assert_eq!(format_number("{}", 42)?, "42");
+        assert_eq!(format_number("ORD-{:06}", 42)?, "ORD-000042");
+        assert_eq!(format_number("[{:4}]", 42)?, "[  42]");
+        assert!(format_number("no placeholder", 42).is_err());
+        assert!(format_number("{:x}", 42).is_err());
+        Ok(())
+    }
+}
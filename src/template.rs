@@ -0,0 +1,282 @@
+use anyhow::{Result, anyhow, bail};
+use fake::Fake;
+use fake::uuid::UUIDv4;
+use rand::Rng;
+
+use crate::expr::{self, ExprValue};
+use crate::fakers::{self, Faker};
+use crate::locales::Locale;
+use crate::pattern::Pattern;
+use crate::sequence::format_number;
+
+/// Short names for common fakers, like `{word}` for `{lorem.word}`
+pub const CATEGORIES: &[(&str, &str)] = &[
+    ("word", "lorem.word"),
+    ("first_name", "name.first_name"),
+    ("last_name", "name.last_name"),
+    ("name", "name.name"),
+    ("company", "company.company_name"),
+    ("city", "address.city_name"),
+    ("country", "address.country_name"),
+    ("street", "address.street_name"),
+    ("zip", "address.zip_code"),
+    ("email", "internet.safe_email"),
+    ("username", "internet.username"),
+    ("digit", "number.digit"),
+];
+
+/// Where a template placeholder's value comes from
+#[derive(Debug)]
+pub enum Source {
+    /// The field's sequence number, with a format like `{:06}`
+    Seq(Option<String>),
+    Pool(String),
+    /// A field of the message by dotted path, `parent.` for the parent message
+    Field(Vec<String>),
+    Pattern(Pattern),
+    Fake(&'static Faker),
+    Uuid,
+    Letter,
+}
+
+#[derive(Debug)]
+enum Segment {
+    Text(String),
+    /// A value passed through functions, innermost first
+    Placeholder {
+        functions: Vec<String>,
+        source: Source,
+    },
+}
+
+/// A parsed `template` option: literal text with `{...}` placeholders
+#[derive(Debug)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    pub fn parse(input: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut rest = input;
+
+        while let Some(c) = rest.chars().next() {
+            if rest.starts_with("{{") || rest.starts_with("}}") {
+                text.push(c);
+                rest = &rest[2..];
+                continue;
+            }
+            match c {
+                '{' => {
+                    let end = placeholder_end(&rest[1..])
+                        .ok_or_else(|| anyhow!("Unclosed placeholder in template {:?}", input))?
+                        + 1;
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    segments.push(
+                        placeholder(&rest[1..end])
+                            .map_err(|e| anyhow!("Template {:?}: {}", input, e))?,
+                    );
+                    rest = &rest[end + 1..];
+                }
+                '}' => bail!(
+                    "Unmatched }} in template {:?}, write }}}} for a literal brace",
+                    input
+                ),
+                _ => {
+                    text.push(c);
+                    rest = &rest[c.len_utf8()..];
+                }
+            }
+        }
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+
+        Ok(Template { segments })
+    }
+
+    /// The placeholder sources, in order
+    pub fn sources(&self) -> impl Iterator<Item = &Source> {
+        self.segments.iter().filter_map(|segment| match segment {
+            Segment::Placeholder { source, .. } => Some(source),
+            Segment::Text(_) => None,
+        })
+    }
+
+    /// Fill in the template. Sequence numbers, pools and fields are read through `resolve`,
+    /// patterns and fake data (in `locale`) are generated here.
+    pub fn render<R: Rng + ?Sized>(
+        &self,
+        locale: Locale,
+        rng: &mut R,
+        mut resolve: impl FnMut(&Source, &mut R) -> Result<ExprValue>,
+    ) -> Result<String> {
+        let mut out = String::new();
+
+        for segment in &self.segments {
+            let (functions, source) = match segment {
+                Segment::Text(text) => {
+                    out.push_str(text);
+                    continue;
+                }
+                Segment::Placeholder { functions, source } => (functions, source),
+            };
+
+            let mut value = match source {
+                Source::Pattern(pattern) => ExprValue::Str(pattern.generate(rng)),
+                Source::Fake(faker) => ExprValue::Str(faker.generate(locale, rng)),
+                Source::Uuid => ExprValue::Str(UUIDv4.fake_with_rng(rng)),
+                Source::Letter => {
+                    ExprValue::Str(char::from(rng.random_range(b'A'..=b'Z')).to_string())
+                }
+                Source::Seq(Some(format)) => match resolve(source, rng)? {
+                    ExprValue::Int(number) => ExprValue::Str(format_number(format, number)?),
+                    value => value,
+                },
+                source => resolve(source, rng)?,
+            };
+            for function in functions {
+                value = expr::call(function, vec![value], rng)?;
+            }
+            out.push_str(&value.to_string());
+        }
+
+        Ok(out)
+    }
+}
+
+/// Find the `}` closing a placeholder in `rest` (the text after its `{`). A regex between
+/// slashes may contain braces of its own.
+fn placeholder_end(rest: &str) -> Option<usize> {
+    let close = rest.find('}')?;
+    let slash = match rest[..close].find('/') {
+        Some(slash) => slash,
+        None => return Some(close),
+    };
+
+    let mut from = slash + 1;
+    loop {
+        let next = from + rest[from..].find('/')?;
+        let after = rest[next + 1..].trim_start_matches(')');
+        if after.starts_with('}') {
+            return Some(rest.len() - after.len());
+        }
+        from = next + 1;
+    }
+}
+
+/// Parse the inside of a placeholder, like `upper(pool:regions)`
+fn placeholder(body: &str) -> Result<Segment> {
+    let mut functions = Vec::new();
+    let mut inner = body.trim();
+
+    while !inner.starts_with('/')
+        && let Some((name, arg)) = inner.strip_suffix(')').and_then(|s| s.split_once('('))
+    {
+        let name = name.trim();
+        expr::check_call(name, 1)?;
+        functions.push(name.to_string());
+        inner = arg.trim();
+    }
+    functions.reverse();
+
+    let source = if let Some(regex) = inner.strip_prefix('/').and_then(|s| s.strip_suffix('/')) {
+        Source::Pattern(Pattern::new(regex)?)
+    } else if inner == "seq" {
+        Source::Seq(None)
+    } else if let Some(spec) = inner.strip_prefix("seq:") {
+        let format = format!("{{:{}}}", spec);
+        format_number(&format, 0).map_err(|_| {
+            anyhow!(
+                "Invalid sequence format {{{}}}, expected e.g. {{seq:06}}",
+                inner
+            )
+        })?;
+        Source::Seq(Some(format))
+    } else if let Some(name) = inner.strip_prefix("pool:") {
+        if name.is_empty() {
+            bail!("{{pool:}} needs a pool name");
+        }
+        Source::Pool(name.to_string())
+    } else if let Some(path) = inner.strip_prefix("field:") {
+        let path: Vec<String> = path.split('.').map(str::to_string).collect();
+        if path.iter().any(String::is_empty) {
+            bail!("Invalid field path {{{}}}", inner);
+        }
+        Source::Field(path)
+    } else if inner == "uuid" {
+        Source::Uuid
+    } else if inner == "letter" {
+        Source::Letter
+    } else if let Some((_, name)) = CATEGORIES.iter().find(|(short, _)| *short == inner) {
+        Source::Fake(fakers::find(name)?)
+    } else if inner.contains('.') {
+        Source::Fake(fakers::find(inner)?)
+    } else {
+        let short: Vec<&str> = CATEGORIES.iter().map(|(short, _)| *short).collect();
+        bail!(
+            "Unknown placeholder {{{}}}, expected seq, pool:NAME, field:PATH, /regex/, a faker \
+             like address.city_name or one of {}, uuid, letter",
+            inner,
+            short.join(", ")
+        );
+    };
+
+    Ok(Segment::Placeholder { functions, source })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(input: &str) -> Result<String> {
+        Template::parse(input)?.render(Locale::En, &mut rand::rng(), |source, _| match source {
+            Source::Seq(_) => Ok(ExprValue::Int(42)),
+            Source::Pool(name) if name == "regions" => Ok(ExprValue::Str("eu-west".to_string())),
+            Source::Field(path) if path.join(".") == "customer.first_name" => {
+                Ok(ExprValue::Str("Ada".to_string()))
+            }
+            source => bail!("Can't resolve {:?}", source),
+        })
+    }
+
+    #[test]
+    fn test_render() -> Result<()> {
+        assert_eq!(render("ORD-{seq:06}")?, "ORD-000042");
+        assert_eq!(render("{seq}@{upper(pool:regions)}")?, "42@EU-WEST");
+        assert_eq!(render("{lower(field:customer.first_name)}")?, "ada");
+        assert_eq!(render("{{literal}} {seq}")?, "{literal} 42");
+
+        let id = render("{/[A-Z]{3}/}-{letter}{digit}")?;
+        assert_eq!(id.len(), 6);
+        assert!(id[..3].chars().all(|c| c.is_ascii_uppercase()));
+        assert_eq!(&id[3..4], "-");
+
+        let word = render("{upper(word)}")?;
+        assert!(!word.is_empty());
+        assert_eq!(word, word.to_uppercase());
+        assert!(!render("{address.city_name}, {country}")?.starts_with(", "));
+
+        let length = render("{len(/a{4}/)}")?;
+        assert_eq!(length, "4");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Template::parse("{seq").is_err());
+        assert!(Template::parse("a}b").is_err());
+        assert!(Template::parse("{nonsense}").is_err());
+        assert!(Template::parse("{address.nowhere}").is_err());
+        assert!(Template::parse("{shout(word)}").is_err());
+        assert!(Template::parse("{concat(word)}").is_ok());
+        assert!(Template::parse("{max(word)}").is_err());
+        assert!(Template::parse("{seq:x}").is_err());
+        assert!(Template::parse("{pool:}").is_err());
+        assert!(Template::parse("{/[/}").is_err());
+    }
+}
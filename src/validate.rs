@@ -0,0 +1,518 @@
+use prost_reflect::{DynamicMessage, FieldDescriptor, Kind, Value};
+use rand::Rng;
+use rand::seq::IndexedRandom;
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use crate::option_parser;
+use crate::overlay;
+
+/// Full name of the protovalidate extension carrying field rules
+const FIELD_RULES_EXTENSION: &str = "buf.validate.field";
+
+/// Constraints read from a field's `(buf.validate.field)` options.
+///
+/// Numeric bounds are stored inclusive: exclusive `gt`/`lt` rules are shifted to the
+/// next representable value of the field's type when they're read.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FieldRules {
+    pub required: bool,
+    pub min: Option<option_parser::Value>,
+    pub max: Option<option_parser::Value>,
+    pub min_len: Option<u64>,
+    pub max_len: Option<u64>,
+    pub pattern: Option<String>,
+    pub prefix: Option<String>,
+    pub suffix: Option<String>,
+    pub one_of: Vec<String>,
+    pub well_known: Option<&'static str>,
+    pub min_items: Option<u64>,
+    pub max_items: Option<u64>,
+    pub unique_items: bool,
+    pub defined_only: bool,
+    pub enum_in: Vec<i32>,
+    pub enum_not_in: Vec<i32>,
+    pub items: Option<Box<FieldRules>>,
+}
+
+/// A deliberate way of breaking a field's rules
+#[derive(Debug, Clone, PartialEq)]
+pub enum Violation {
+    /// Leave the field unset
+    Unset,
+    /// Set the field to this value
+    Value(Value),
+    /// Generate the field with these rule-breaking options
+    Options(HashMap<String, option_parser::Value>),
+}
+
+impl FieldRules {
+    /// Read the rules attached to a field, if the pool knows about protovalidate and the
+    /// field carries any.
+    pub fn from_field(field: &FieldDescriptor) -> Option<FieldRules> {
+        let extension = field
+            .parent_pool()
+            .get_extension_by_name(FIELD_RULES_EXTENSION)?;
+        let options = field.options();
+        if !options.has_extension(&extension) {
+            return None;
+        }
+
+        let rules = options.get_extension(&extension);
+        Some(FieldRules::from_message(rules.as_message()?, &field.kind()))
+    }
+
+    fn from_message(message: &DynamicMessage, kind: &Kind) -> FieldRules {
+        let mut rules = FieldRules {
+            required: get_bool(message, "required"),
+            ..Default::default()
+        };
+
+        if let Some(string) = get_message(message, "string") {
+            rules.min_len = get_u64(&string, "min_len");
+            rules.max_len = get_u64(&string, "max_len");
+            if let Some(len) = get_u64(&string, "len") {
+                rules.min_len = Some(len);
+                rules.max_len = Some(len);
+            }
+            rules.pattern = get_string(&string, "pattern");
+            rules.prefix = get_string(&string, "prefix");
+            rules.suffix = get_string(&string, "suffix");
+            rules.one_of = match get_string(&string, "const") {
+                Some(c) => vec![c],
+                None => get_field(&string, "in")
+                    .and_then(|v| {
+                        v.as_list().map(|l| {
+                            l.iter()
+                                .filter_map(|s| s.as_str().map(str::to_string))
+                                .collect()
+                        })
+                    })
+                    .unwrap_or_default(),
+            };
+            rules.well_known = [
+                "email", "hostname", "ip", "ipv4", "ipv6", "uri", "uri_ref", "address", "uuid",
+            ]
+            .into_iter()
+            .find(|name| get_bool(&string, name));
+        } else if let Some(bytes) = get_message(message, "bytes") {
+            rules.min_len = get_u64(&bytes, "min_len");
+            rules.max_len = get_u64(&bytes, "max_len");
+            if let Some(len) = get_u64(&bytes, "len") {
+                rules.min_len = Some(len);
+                rules.max_len = Some(len);
+            }
+        } else if let Some(enum_rules) = get_message(message, "enum") {
+            rules.defined_only = get_bool(&enum_rules, "defined_only");
+            let numbers = |name| {
+                get_field(&enum_rules, name)
+                    .and_then(|v| {
+                        v.as_list()
+                            .map(|l| l.iter().filter_map(|n| n.as_i32()).collect())
+                    })
+                    .unwrap_or_default()
+            };
+            rules.enum_in = match get_field(&enum_rules, "const").and_then(|v| v.as_i32()) {
+                Some(c) => vec![c],
+                None => numbers("in"),
+            };
+            rules.enum_not_in = numbers("not_in");
+        } else if let Some(repeated) = get_message(message, "repeated") {
+            rules.min_items = get_u64(&repeated, "min_items");
+            rules.max_items = get_u64(&repeated, "max_items");
+            rules.unique_items = get_bool(&repeated, "unique");
+            rules.items = get_message(&repeated, "items")
+                .map(|items| Box::new(FieldRules::from_message(&items, kind)));
+        } else if let Some(numeric) = [
+            "float", "double", "int32", "int64", "uint32", "uint64", "sint32", "sint64", "fixed32",
+            "fixed64", "sfixed32", "sfixed64",
+        ]
+        .into_iter()
+        .find_map(|name| get_message(message, name))
+        {
+            rules.read_numeric_bounds(&numeric, kind);
+        }
+
+        rules
+    }
+
+    fn read_numeric_bounds(&mut self, numeric: &DynamicMessage, kind: &Kind) {
+        let get = |name: &str| get_field(numeric, name).and_then(|v| as_f64(&v));
+
+        if matches!(kind, Kind::Float | Kind::Double) {
+            // Exclusive bounds move by one ulp of the field's own precision, since a value
+            // one f64 ulp away would round back onto the bound as an f32
+            let is_f32 = matches!(kind, Kind::Float);
+            let next_up = |bound: f64| {
+                if is_f32 {
+                    (bound as f32).next_up() as f64
+                } else {
+                    bound.next_up()
+                }
+            };
+            let next_down = |bound: f64| {
+                if is_f32 {
+                    (bound as f32).next_down() as f64
+                } else {
+                    bound.next_down()
+                }
+            };
+            self.min = get("gte")
+                .or_else(|| get("gt").map(next_up))
+                .or_else(|| get("const"))
+                .map(option_parser::Value::Float);
+            self.max = get("lte")
+                .or_else(|| get("lt").map(next_down))
+                .or_else(|| get("const"))
+                .map(option_parser::Value::Float);
+        } else {
+            let get = |name: &str| get_field(numeric, name).and_then(|v| as_i128(&v));
+            self.min = get("gte")
+                .or_else(|| get("gt").map(|v| v + 1))
+                .or_else(|| get("const"))
+                .map(option_parser::Value::integer);
+            self.max = get("lte")
+                .or_else(|| get("lt").map(|v| v - 1))
+                .or_else(|| get("const"))
+                .map(option_parser::Value::integer);
+        }
+    }
+
+    /// Narrow generator options so generated values satisfy these rules.
+    ///
+    /// Bounds are intersected with any bounds already present; everything else the rules
+    /// dictate replaces the comment options. A rule choosing a generator (`pattern`, a
+    /// well-known string format, `in`) replaces the field's own generator options, as an
+    /// overlay does, so a comment `fake` or `pool` can't produce values breaking it.
+    pub fn apply(&self, options: &mut HashMap<String, option_parser::Value>) {
+        if let Some(min) = &self.min {
+            merge_bound(options, "min", min, Bound::Lower);
+        }
+        if let Some(max) = &self.max {
+            merge_bound(options, "max", max, Bound::Upper);
+        }
+        if let Some(min_len) = self.min_len {
+            merge_bound(options, "min_len", &int(min_len), Bound::Lower);
+        }
+        if let Some(max_len) = self.max_len {
+            merge_bound(options, "max_len", &int(max_len), Bound::Upper);
+        }
+        let mut generators = HashMap::new();
+        if let Some(pattern) = &self.pattern {
+            generators.insert("pattern".to_string(), text(pattern));
+        }
+        if let Some(well_known) = self.well_known {
+            generators.insert("string".to_string(), text(well_known));
+        }
+        if !self.one_of.is_empty() {
+            generators.insert(
+                "words".to_string(),
+                option_parser::Value::ListStr(self.one_of.clone()),
+            );
+        }
+        if !self.enum_in.is_empty() {
+            generators.insert(
+                "values".to_string(),
+                option_parser::Value::ListInt(self.enum_in.iter().map(|n| *n as i64).collect()),
+            );
+        }
+        overlay::merge(options, &generators);
+        if let Some(prefix) = &self.prefix {
+            options.insert("prefix".to_string(), text(prefix));
+        }
+        if let Some(suffix) = &self.suffix {
+            options.insert("suffix".to_string(), text(suffix));
+        }
+        if !self.enum_not_in.is_empty() {
+            // Enum lists accept names and numbers alike, so merge into a list of names
+            let mut exclude = match options.get("exclude") {
+                Some(option_parser::Value::ListStr(names)) => names.clone(),
+                Some(option_parser::Value::ListInt(numbers)) => {
+                    numbers.iter().map(i64::to_string).collect()
+                }
+                _ => Vec::new(),
+            };
+            exclude.extend(self.enum_not_in.iter().map(i32::to_string));
+            options.insert(
+                "exclude".to_string(),
+                option_parser::Value::ListStr(exclude),
+            );
+        }
+        if self.min_items.is_some() || self.max_items.is_some() {
+            let (mut lo, mut hi) = match options.get("count") {
+                Some(option_parser::Value::Int(i)) => (*i, *i),
+                Some(option_parser::Value::Range(s, e)) => (*s, *e),
+                _ => (1, 1),
+            };
+            if let Some(min_items) = self.min_items {
+                lo = lo.max(min_items as i64);
+                hi = hi.max(lo);
+            }
+            if let Some(max_items) = self.max_items {
+                hi = hi.min(max_items as i64);
+                lo = lo.min(hi);
+            }
+            options.insert("count".to_string(), option_parser::Value::Range(lo, hi));
+        }
+        if let Some(items) = &self.items {
+            items.apply(options);
+        }
+    }
+
+    /// Pick one of the rules at random and describe how to break it.
+    ///
+    /// Returns `None` when none of the rules can be violated.
+    pub fn violate<R: Rng + ?Sized>(
+        &self,
+        field: &FieldDescriptor,
+        options: &HashMap<String, option_parser::Value>,
+        rng: &mut R,
+    ) -> Option<Violation> {
+        let mut valid = options.clone();
+        self.apply(&mut valid);
+
+        let mut candidates = Vec::new();
+        self.violations(field, &valid, &mut candidates);
+        if let Some(items) = &self.items {
+            items.violations(field, &valid, &mut candidates);
+        }
+
+        candidates
+            .choose(rng)
+            .cloned()
+            .map(|violation| match violation {
+                Violation::Value(value) if field.is_list() => {
+                    Violation::Value(Value::List(vec![value]))
+                }
+                violation => violation,
+            })
+    }
+
+    fn violations(
+        &self,
+        field: &FieldDescriptor,
+        valid: &HashMap<String, option_parser::Value>,
+        candidates: &mut Vec<Violation>,
+    ) {
+        let with = |changes: &[(&str, option_parser::Value)], remove: &[&str]| {
+            let mut options = valid.clone();
+            for key in remove {
+                options.remove(*key);
+            }
+            for (key, value) in changes {
+                options.insert(key.to_string(), value.clone());
+            }
+            Violation::Options(options)
+        };
+
+        if self.required {
+            candidates.push(Violation::Unset);
+        }
+
+        let unsigned = matches!(
+            field.kind(),
+            Kind::Uint32 | Kind::Uint64 | Kind::Fixed32 | Kind::Fixed64
+        );
+        match &self.min {
+            Some(min @ (option_parser::Value::Int(_) | option_parser::Value::UInt(_))) => {
+                let min = min.as_i128().unwrap_or_default();
+                if !(unsigned && min <= 0) {
+                    candidates.push(with(
+                        &[
+                            ("min", option_parser::Value::integer(min - 100)),
+                            ("max", option_parser::Value::integer(min - 1)),
+                        ],
+                        &[],
+                    ))
+                }
+            }
+            Some(option_parser::Value::Float(min)) => candidates.push(with(
+                &[
+                    ("min", option_parser::Value::Float(min - 100.0)),
+                    ("max", option_parser::Value::Float(min.next_down())),
+                ],
+                &[],
+            )),
+            _ => (),
+        }
+        match &self.max {
+            Some(max @ (option_parser::Value::Int(_) | option_parser::Value::UInt(_))) => {
+                let max = max.as_i128().unwrap_or_default();
+                candidates.push(with(
+                    &[
+                        ("min", option_parser::Value::integer(max + 1)),
+                        ("max", option_parser::Value::integer(max + 100)),
+                    ],
+                    &[],
+                ))
+            }
+            Some(option_parser::Value::Float(max)) => candidates.push(with(
+                &[
+                    ("min", option_parser::Value::Float(max.next_up())),
+                    ("max", option_parser::Value::Float(max + 100.0)),
+                ],
+                &[],
+            )),
+            _ => (),
+        }
+
+        match self.min_len {
+            Some(min_len) if min_len > 0 => candidates.push(with(
+                &[("min_len", int(0)), ("max_len", int(min_len - 1))],
+                &["prefix", "suffix"],
+            )),
+            _ => (),
+        }
+        if let Some(max_len) = self.max_len {
+            candidates.push(with(
+                &[
+                    ("min_len", int(max_len + 1)),
+                    ("max_len", int(max_len + 10)),
+                ],
+                &[],
+            ));
+        }
+
+        if let Some(well_known) = self.well_known {
+            candidates.push(Violation::Value(Value::String(format!(
+                "not a valid {}",
+                well_known
+            ))));
+        }
+        if self.pattern.is_some() {
+            // Whitespace and punctuation rule out the vast majority of real-world patterns
+            candidates.push(with(
+                &[(
+                    "words",
+                    option_parser::Value::ListStr(vec!["<not matching>".to_string()]),
+                )],
+                &["pattern", "string"],
+            ));
+        }
+        if self.prefix.is_some() || self.suffix.is_some() {
+            candidates.push(with(&[], &["prefix", "suffix"]));
+        }
+
+        match self.min_items {
+            Some(min_items) if min_items > 0 => candidates.push(with(
+                &[(
+                    "count",
+                    option_parser::Value::Range(0, min_items as i64 - 1),
+                )],
+                &[],
+            )),
+            _ => (),
+        }
+        if let Some(max_items) = self.max_items {
+            candidates.push(with(
+                &[(
+                    "count",
+                    option_parser::Value::Range(max_items as i64 + 1, max_items as i64 + 3),
+                )],
+                &[],
+            ));
+        }
+
+        if self.defined_only
+            && let Kind::Enum(enum_type) = field.kind()
+        {
+            let undefined = enum_type.values().map(|v| v.number()).max().unwrap_or(0) + 1;
+            candidates.push(Violation::Value(Value::EnumNumber(undefined)));
+        }
+    }
+}
+
+fn int(v: u64) -> option_parser::Value {
+    option_parser::Value::Int(v as i64)
+}
+
+fn text(s: &str) -> option_parser::Value {
+    option_parser::Value::Str(s.to_string())
+}
+
+/// Which side of a range a bound is on, so merging keeps the tighter one
+#[derive(Clone, Copy)]
+enum Bound {
+    Lower,
+    Upper,
+}
+
+fn merge_bound(
+    options: &mut HashMap<String, option_parser::Value>,
+    key: &str,
+    bound: &option_parser::Value,
+    side: Bound,
+) {
+    let pick = match side {
+        Bound::Lower => f64::max,
+        Bound::Upper => f64::min,
+    };
+    let as_f64 = |value: &option_parser::Value| match value {
+        option_parser::Value::Float(f) => Some(*f),
+        value => value.as_i128().map(|i| i as f64),
+    };
+    let merged = match options.get(key) {
+        // Integers are compared exactly, since `uint64` bounds don't all fit in an f64
+        Some(current) if current.as_i128().is_some() && bound.as_i128().is_some() => {
+            let (a, b) = (current.as_i128().unwrap(), bound.as_i128().unwrap());
+            option_parser::Value::integer(match side {
+                Bound::Lower => a.max(b),
+                Bound::Upper => a.min(b),
+            })
+        }
+        Some(current) => match (as_f64(current), as_f64(bound)) {
+            (Some(a), Some(b)) => option_parser::Value::Float(pick(a, b)),
+            _ => bound.clone(),
+        },
+        None => bound.clone(),
+    };
+    options.insert(key.to_string(), merged);
+}
+
+fn get_field<'a>(message: &'a DynamicMessage, name: &str) -> Option<Cow<'a, Value>> {
+    if message.has_field_by_name(name) {
+        message.get_field_by_name(name)
+    } else {
+        None
+    }
+}
+
+fn get_message(message: &DynamicMessage, name: &str) -> Option<DynamicMessage> {
+    get_field(message, name).and_then(|v| v.as_message().cloned())
+}
+
+fn get_bool(message: &DynamicMessage, name: &str) -> bool {
+    get_field(message, name)
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+}
+
+fn get_u64(message: &DynamicMessage, name: &str) -> Option<u64> {
+    get_field(message, name).and_then(|v| v.as_u64())
+}
+
+fn get_string(message: &DynamicMessage, name: &str) -> Option<String> {
+    get_field(message, name).and_then(|v| v.as_str().map(str::to_string))
+}
+
+fn as_f64(value: &Value) -> Option<f64> {
+    match value {
+        Value::I32(v) => Some(*v as f64),
+        Value::I64(v) => Some(*v as f64),
+        Value::U32(v) => Some(*v as f64),
+        Value::U64(v) => Some(*v as f64),
+        Value::F32(v) => Some(*v as f64),
+        Value::F64(v) => Some(*v),
+        _ => None,
+    }
+}
+
+fn as_i128(value: &Value) -> Option<i128> {
+    match value {
+        Value::I32(v) => Some(i128::from(*v)),
+        Value::I64(v) => Some(i128::from(*v)),
+        Value::U32(v) => Some(i128::from(*v)),
+        Value::U64(v) => Some(i128::from(*v)),
+        _ => None,
+    }
+}